@@ -1,6 +1,42 @@
 use std::path::PathBuf;
 
+/// Reads the top-level `key` from the axconfig file at `AX_CONFIG_PATH`, if
+/// both exist.
+fn config_value(key: &str) -> Option<String> {
+    let path = std::env::var("AX_CONFIG_PATH").ok()?;
+    println!("cargo:rerun-if-changed={path}");
+    let text = std::fs::read_to_string(path).ok()?;
+    text.lines()
+        .take_while(|line| !line.trim_start().starts_with('['))
+        .find_map(|line| {
+            let (k, value) = line.split_once('=')?;
+            let value = value.split('#').next()?.trim().trim_matches('"');
+            (k.trim() == key).then(|| value.to_string())
+        })
+}
+
 fn main() {
+    // Kernel log level, read by `main()` via `env!("APP_LOG_LEVEL")`.
+    println!("cargo:rerun-if-env-changed=AX_CONFIG_PATH");
+    let log_level = config_value("log-level").unwrap_or_else(|| "info".into());
+    println!("cargo:rustc-env=APP_LOG_LEVEL={log_level}");
+
+    // User address space range, included by `task.rs`. The config's integer
+    // literals (underscores included) are valid Rust as they are.
+    let uspace_base = config_value("uspace-base").unwrap_or_else(|| "0x0".into());
+    let uspace_size = config_value("uspace-size").unwrap_or_else(|| "0x40_0000_0000".into());
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(
+        PathBuf::from(&out_dir).join("uspace.rs"),
+        format!(
+            "/// Base of the user address space.\n\
+             pub const USER_ASPACE_BASE: usize = {uspace_base};\n\
+             /// Size of the user address space.\n\
+             pub const USER_ASPACE_SIZE: usize = {uspace_size};\n"
+        ),
+    )
+    .unwrap();
+
     // Only apply bare-metal linker settings when targeting a no_std platform.
     // This allows `cargo publish` verification (which builds for the host) to succeed.
     let target = std::env::var("TARGET").unwrap_or_default();
@@ -13,12 +49,14 @@ fn main() {
         println!("cargo:rustc-link-arg=-T{}/payload/linker.ld", manifest_dir);
         println!("cargo:rustc-link-arg=-nostdlib");
         println!("cargo:rustc-link-arg=-static");
+        // The kernel applies no relocations, so link a fixed-address
+        // executable even on targets that default to PIE (x86_64).
+        println!("cargo:rustc-link-arg=--no-pie");
         return;
     }
 
     // The linker script is generated by axhal's build.rs at:
     //   target/<target_triple>/<profile>/linker_<platform>.lds
-    let out_dir = std::env::var("OUT_DIR").unwrap();
     let profile_dir = PathBuf::from(&out_dir).join("../../..");
     let profile_dir = std::fs::canonicalize(&profile_dir)
         .unwrap_or_else(|_| PathBuf::from(&out_dir).join("../../.."));
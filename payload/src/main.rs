@@ -1,31 +1,322 @@
-//! Minimal user-space binary that calls SYS_EXIT(0).
+//! Minimal user-space program for the monolithic kernel.
 //!
 //! This is the "payload" loaded by the monolithic kernel into user space.
-//! It simply invokes the exit syscall (number 93) with exit code 0.
+//! Before touching its stack, `_start` scans the 4 KiB just below the
+//! initial stack pointer, which the kernel must hand over zero-filled, and
+//! then calls into ordinary Rust: [`main`] runs with the [`syscall`]
+//! wrappers and its return value becomes the exit code. The exit code has
+//! bit 0 set if the page below the stack was not all zeros, bit 1 set if a
+//! floating-point register was clobbered by a syscall, bit 2 set if
+//! `/sbin/origin` could not be opened relative to the cwd after `chdir`,
+//! bit 3 set if stdout did not report a terminal window size, bit 4 set if
+//! the initial stack pointer was not 16-byte aligned, and bit 5 set if a
+//! `.data` or `.bss` static did not hold its initial value. After the other
+//! checks, [`main`] spins until the kernel releases it from another task,
+//! which hangs unless the kernel preempts user space on timer interrupts.
+//! If every check passed, the payload ends by sending itself `SIGTERM`
+//! instead of exiting, so a passing run exits with 143 (`128 + SIGTERM`);
+//! exit code 64 (bit 6) means it survived the signal.
 
 #![no_std]
 #![no_main]
 
+#[macro_use]
+mod syscall;
+
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use syscall::{
+    AT_FDCWD, ENODEV, O_RDONLY, SIGTERM, TIOCGWINSZ, sys_chdir, sys_close, sys_exit, sys_getpid,
+    sys_ioctl, sys_kill, sys_openat, sys_write,
+};
+
+fn main(release: &AtomicU32) -> i32 {
+    let mut status = 0;
+    if fp_survives_syscall() {
+        sys_write(1, b"payload: FP state survived a syscall\n");
+    } else {
+        sys_write(2, b"payload: FP register clobbered by a syscall\n");
+        status |= 2;
+    }
+    if !cwd_relative_open() {
+        status |= 4;
+    }
+    if !stdout_has_winsize() {
+        status |= 8;
+    }
+    if !statics_writable() {
+        status |= 32;
+    }
+    // Last, so a kernel that never preempts user space still gets the other
+    // checks reported before it hangs.
+    wait_for_release(release);
+    status
+}
+
+/// A `.data` static, loaded from the file, and a `.bss` one, zero-filled.
+static DATA: AtomicU32 = AtomicU32::new(7);
+static BSS: AtomicU32 = AtomicU32::new(0);
+
+/// Increment both statics, which faults unless the kernel maps the image's
+/// data segment writable, and check they started from their initial values.
+fn statics_writable() -> bool {
+    let data = core::hint::black_box(&DATA).fetch_add(1, Ordering::Relaxed);
+    let bss = core::hint::black_box(&BSS).fetch_add(1, Ordering::Relaxed);
+    if data != 7 || bss != 0 {
+        sys_write(
+            2,
+            b"payload: statics did not start at their initial values\n",
+        );
+        return false;
+    }
+    sys_write(1, b"payload: wrote a .data and a .bss static\n");
+    true
+}
+
+/// Mark `release` 1 (spinning), then spin, making no syscalls, until another
+/// kernel task sets it to 2. That task only runs if a timer interrupt
+/// preempts this loop, so without preemption the payload never gets past it.
+fn wait_for_release(release: &AtomicU32) {
+    release.store(1, Ordering::Release);
+    while release.load(Ordering::Acquire) != 2 {
+        core::hint::spin_loop();
+    }
+    sys_write(1, b"payload: released by another task while spinning\n");
+}
+
+/// Ask for stdout's window size, as CLI programs do to detect a terminal.
+fn stdout_has_winsize() -> bool {
+    // `struct winsize { u16 ws_row, ws_col, ws_xpixel, ws_ypixel; }`
+    let mut winsize = [0u16; 4];
+    if sys_ioctl(1, TIOCGWINSZ, winsize.as_mut_ptr().cast()) != 0 || winsize[1] == 0 {
+        sys_write(2, b"payload: stdout is not a terminal\n");
+        return false;
+    }
+    sys_write(1, b"payload: stdout is a terminal\n");
+    true
+}
+
+/// `chdir` into `/sbin` and open this payload there by its relative name.
+/// Passes trivially when the kernel has no filesystem (initrd boot), which
+/// it reports as `-ENODEV`; any other failure, `-ENOENT` included, fails.
+fn cwd_relative_open() -> bool {
+    match sys_chdir(c"/sbin") {
+        0 => {}
+        err if err == -ENODEV => {
+            sys_write(1, b"payload: no filesystem, skipped the chdir check\n");
+            return true;
+        }
+        _ => {
+            sys_write(2, b"payload: chdir(\"/sbin\") failed\n");
+            return false;
+        }
+    }
+    let fd = sys_openat(AT_FDCWD, c"origin", O_RDONLY);
+    if fd < 0 {
+        sys_write(2, b"payload: cannot open origin relative to /sbin\n");
+        return false;
+    }
+    sys_close(fd as usize);
+    sys_write(1, b"payload: opened origin relative to /sbin\n");
+    true
+}
+
+/// Called by `_start` with whether the page below the stack was dirty, the
+/// initial stack pointer, and the kernel's first argument: the address of
+/// the word [`wait_for_release`] spins on.
+extern "C" fn entry(stack_dirty: usize, sp: usize, release: *const AtomicU32) -> ! {
+    let mut status = main(unsafe { &*release }) | stack_dirty as i32;
+    if !sp.is_multiple_of(16) {
+        sys_write(
+            2,
+            b"payload: initial stack pointer is not 16-byte aligned\n",
+        );
+        status |= 16;
+    }
+    if status == 0 {
+        kill_self();
+        status = 64;
+    }
+    sys_exit(status)
+}
+
+/// `kill(getpid(), SIGTERM)`, which should terminate the process before the
+/// syscall returns to user space.
+fn kill_self() {
+    sys_write(1, b"payload: sending SIGTERM to itself\n");
+    let ret = sys_kill(sys_getpid(), SIGTERM);
+    if ret != 0 {
+        sys_write(2, b"payload: kill(getpid(), SIGTERM) failed\n");
+    } else {
+        sys_write(2, b"payload: still running after SIGTERM\n");
+    }
+}
+
+#[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn _start() -> ! {
+    core::arch::naked_asm!(
+        "mv a2, a0",
+        "mv a1, sp",
+        "mv t0, sp",
+        "li t1, 4096",
+        "sub t1, t0, t1",
+        "li a0, 0",
+        "2:",
+        "ld t2, 0(t1)",
+        "or a0, a0, t2",
+        "addi t1, t1, 8",
+        "bltu t1, t0, 2b",
+        "snez a0, a0",
+        "j {entry}",
+        entry = sym entry,
+    )
+}
+
+#[cfg(target_arch = "aarch64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn _start() -> ! {
+    core::arch::naked_asm!(
+        "mov x2, x0",
+        "mov x1, sp",
+        "mov x9, sp",
+        "sub x10, x9, #4096",
+        "mov x0, #0",
+        "2:",
+        "ldr x11, [x10], #8",
+        "orr x0, x0, x11",
+        "cmp x10, x9",
+        "b.lo 2b",
+        "cmp x0, #0",
+        "cset x0, ne",
+        "b {entry}",
+        entry = sym entry,
+    )
+}
 
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
 #[unsafe(no_mangle)]
 unsafe extern "C" fn _start() -> ! {
-    unsafe {
-        #[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))]
-        core::arch::asm!("li a7, 93", "ecall", options(noreturn));
+    core::arch::naked_asm!(
+        "mov rdx, rdi",
+        "mov rsi, rsp",
+        "mov rcx, rsp",
+        "lea r8, [rsp - 4096]",
+        "xor edi, edi",
+        "2:",
+        "or rdi, [r8]",
+        "add r8, 8",
+        "cmp r8, rcx",
+        "jb 2b",
+        "test rdi, rdi",
+        "setnz dil",
+        "movzx edi, dil",
+        // `call` leaves the stack aligned as a function entry expects.
+        "call {entry}",
+        entry = sym entry,
+    )
+}
 
-        #[cfg(target_arch = "aarch64")]
-        core::arch::asm!("mov x8, #93", "svc #0", options(noreturn));
+#[cfg(target_arch = "loongarch64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn _start() -> ! {
+    core::arch::naked_asm!(
+        "move $a2, $a0",
+        "move $a1, $sp",
+        "move $t0, $sp",
+        "addi.d $t1, $sp, -2048",
+        "addi.d $t1, $t1, -2048",
+        "move $a0, $zero",
+        "2:",
+        "ld.d $t2, $t1, 0",
+        "or $a0, $a0, $t2",
+        "addi.d $t1, $t1, 8",
+        "bltu $t1, $t0, 2b",
+        "sltu $a0, $zero, $a0",
+        "b {entry}",
+        entry = sym entry,
+    )
+}
 
-        #[cfg(target_arch = "x86_64")]
-        core::arch::asm!("mov rax, 93", "syscall", options(noreturn));
+// `fp_survives_syscall` computes 1.5 + 1.5 in a caller-saved FP register,
+// makes a `getpid` syscall, and returns whether the register still converts
+// to 3. It is written in assembly because the payload targets may be
+// soft-float, so Rust code would never touch the FP registers itself.
 
-        #[cfg(target_arch = "loongarch64")]
-        core::arch::asm!("ori $a7, $zero, 93", "syscall 0", options(noreturn));
-    }
+#[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))]
+#[unsafe(naked)]
+extern "C" fn fp_survives_syscall() -> bool {
+    core::arch::naked_asm!(
+        "li t3, 0x3ff8000000000000",
+        "fmv.d.x ft0, t3",
+        "fadd.d ft0, ft0, ft0",
+        "li a7, 172",
+        "ecall",
+        "fcvt.l.d t4, ft0, rtz",
+        "addi t4, t4, -3",
+        "seqz a0, t4",
+        "ret",
+    )
+}
+
+#[cfg(target_arch = "aarch64")]
+#[unsafe(naked)]
+extern "C" fn fp_survives_syscall() -> bool {
+    core::arch::naked_asm!(
+        ".arch_extension fp",
+        "movz x9, #0x3ff8, lsl #48",
+        "fmov d0, x9",
+        "fadd d0, d0, d0",
+        "mov x8, #172",
+        "svc #0",
+        "fcvtzs x10, d0",
+        "cmp x10, #3",
+        "cset x0, eq",
+        "ret",
+    )
+}
+
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
+extern "C" fn fp_survives_syscall() -> bool {
+    core::arch::naked_asm!(
+        "mov rax, 0x3ff8000000000000",
+        "movq xmm0, rax",
+        "addsd xmm0, xmm0",
+        "mov eax, 172",
+        "syscall",
+        "cvttsd2si rax, xmm0",
+        "cmp rax, 3",
+        "sete al",
+        "ret",
+    )
+}
+
+#[cfg(target_arch = "loongarch64")]
+#[unsafe(naked)]
+extern "C" fn fp_survives_syscall() -> bool {
+    core::arch::naked_asm!(
+        "move $t3, $zero",
+        "lu32i.d $t3, -0x80000",
+        "lu52i.d $t3, $t3, 0x3ff",
+        "movgr2fr.d $f0, $t3",
+        "fadd.d $f0, $f0, $f0",
+        "ori $a7, $zero, 172",
+        "syscall 0",
+        "ftintrz.l.d $f1, $f0",
+        "movfr2gr.d $t4, $f1",
+        "addi.d $t4, $t4, -3",
+        "sltui $a0, $t4, 1",
+        "jr $ra",
+    )
 }
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+    sys_exit(-1)
 }
@@ -0,0 +1,163 @@
+//! Thin syscall wrappers for the payload.
+//!
+//! [`syscall!`] issues the raw syscall instruction of the target
+//! architecture (`ecall`, `svc #0`, `syscall` or `syscall 0`). Numbers
+//! follow the generic Linux table on every architecture, like the kernel.
+
+use core::ffi::CStr;
+
+pub const SYS_IOCTL: usize = 29;
+pub const SYS_CHDIR: usize = 49;
+pub const SYS_OPENAT: usize = 56;
+pub const SYS_CLOSE: usize = 57;
+pub const SYS_WRITE: usize = 64;
+pub const SYS_EXIT: usize = 93;
+pub const SYS_KILL: usize = 129;
+pub const SYS_GETPID: usize = 172;
+
+/// `openat` directory fd meaning the current working directory.
+pub const AT_FDCWD: isize = -100;
+/// `openat` flag: open for reading only.
+pub const O_RDONLY: usize = 0;
+/// `ioctl` request: get the terminal window size.
+pub const TIOCGWINSZ: usize = 0x5413;
+/// `ENODEV`, as returned (negated) by path syscalls when the kernel has no
+/// filesystem mounted.
+pub const ENODEV: isize = 19;
+/// `SIGTERM`, which the kernel turns into exit code `128 + 15`.
+pub const SIGTERM: usize = 15;
+
+/// Invoke syscall `num` with up to six arguments and return the raw result
+/// (a negative errno on failure).
+macro_rules! syscall {
+    ($num:expr $(, $arg:expr)* $(,)?) => {{
+        // Never written to when there are no arguments.
+        #[allow(unused_mut)]
+        let mut args = [0usize; 6];
+        let mut _i = 0;
+        $(
+            args[_i] = $arg as usize;
+            _i += 1;
+        )*
+        unsafe { $crate::syscall::raw_syscall($num, args) }
+    }};
+}
+
+#[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))]
+pub unsafe fn raw_syscall(num: usize, args: [usize; 6]) -> isize {
+    let ret;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") args[0] => ret,
+            in("a1") args[1],
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+            in("a7") num,
+            options(nostack),
+        );
+    }
+    ret
+}
+
+#[cfg(target_arch = "aarch64")]
+pub unsafe fn raw_syscall(num: usize, args: [usize; 6]) -> isize {
+    let ret;
+    unsafe {
+        core::arch::asm!(
+            "svc #0",
+            inlateout("x0") args[0] => ret,
+            in("x1") args[1],
+            in("x2") args[2],
+            in("x3") args[3],
+            in("x4") args[4],
+            in("x5") args[5],
+            in("x8") num,
+            options(nostack),
+        );
+    }
+    ret
+}
+
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn raw_syscall(num: usize, args: [usize; 6]) -> isize {
+    let ret;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") num => ret,
+            in("rdi") args[0],
+            in("rsi") args[1],
+            in("rdx") args[2],
+            in("r10") args[3],
+            in("r8") args[4],
+            in("r9") args[5],
+            out("rcx") _,
+            out("r11") _,
+            options(nostack),
+        );
+    }
+    ret
+}
+
+#[cfg(target_arch = "loongarch64")]
+pub unsafe fn raw_syscall(num: usize, args: [usize; 6]) -> isize {
+    let ret;
+    unsafe {
+        core::arch::asm!(
+            "syscall 0",
+            inlateout("$a0") args[0] => ret,
+            in("$a1") args[1],
+            in("$a2") args[2],
+            in("$a3") args[3],
+            in("$a4") args[4],
+            in("$a5") args[5],
+            in("$a7") num,
+            options(nostack),
+        );
+    }
+    ret
+}
+
+/// `ioctl(fd, request, argp)`: returns 0 on success.
+pub fn sys_ioctl(fd: usize, request: usize, argp: *mut u8) -> isize {
+    syscall!(SYS_IOCTL, fd, request, argp)
+}
+
+/// `chdir(path)`: returns 0 on success.
+pub fn sys_chdir(path: &CStr) -> isize {
+    syscall!(SYS_CHDIR, path.as_ptr())
+}
+
+/// `openat(dirfd, path, flags)`: returns the new fd.
+pub fn sys_openat(dirfd: isize, path: &CStr, flags: usize) -> isize {
+    syscall!(SYS_OPENAT, dirfd, path.as_ptr(), flags)
+}
+
+/// `close(fd)`: returns 0 on success.
+pub fn sys_close(fd: usize) -> isize {
+    syscall!(SYS_CLOSE, fd)
+}
+
+/// `write(fd, buf, len)`: returns the number of bytes written.
+pub fn sys_write(fd: usize, buf: &[u8]) -> isize {
+    syscall!(SYS_WRITE, fd, buf.as_ptr(), buf.len())
+}
+
+/// `getpid()`: returns the calling process's ID.
+pub fn sys_getpid() -> isize {
+    syscall!(SYS_GETPID)
+}
+
+/// `kill(pid, sig)`: returns 0 on success.
+pub fn sys_kill(pid: isize, sig: usize) -> isize {
+    syscall!(SYS_KILL, pid, sig)
+}
+
+/// `exit(code)`: terminate the calling task.
+pub fn sys_exit(code: i32) -> ! {
+    syscall!(SYS_EXIT, code);
+    unreachable!("SYS_EXIT returned")
+}
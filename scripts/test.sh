@@ -1,6 +1,13 @@
 #!/bin/bash
 set -e
 
+# Seconds before a QEMU run counts as hung (e.g. the payload's preemption
+# check never being released)
+RUN_TIMEOUT=120
+# A payload that passes every check ends by sending itself SIGTERM, which
+# the kernel reports as exit code 128 + 15
+PASS_EXIT_CODE=143
+
 echo "=== ArceOS Childtask Test Script ==="
 echo ""
 
@@ -47,13 +54,16 @@ check_clippy() {
         # Install config file for the architecture
         cp "configs/${arch}.toml" ".axconfig.toml"
         
-        if cargo clippy --target="$target" --features "axstd" -- -D warnings; then
-            echo "✓ $arch clippy check passed"
-        else
-            echo "Error: $arch clippy check failed"
-            rm -f .axconfig.toml
-            exit 1
-        fi
+        # Once per boot mode, as each compiles its own loader
+        for boot in blk initrd; do
+            if cargo clippy --target="$target" --features "$boot" -- -D warnings; then
+                echo "✓ $arch ($boot) clippy check passed"
+            else
+                echo "Error: $arch ($boot) clippy check failed"
+                rm -f .axconfig.toml
+                exit 1
+            fi
+        done
     done
     
     rm -f .axconfig.toml
@@ -67,6 +77,9 @@ check_build() {
     echo "[4/7] Checking basic build (no default features)..."
     cargo check --no-default-features
     echo "✓ Basic build check passed"
+    # Unit tests of the xtask command lines (QEMU arguments, cargo features)
+    cargo test --bin xtask --no-default-features --features xtask
+    echo "✓ xtask tests passed"
     echo ""
 }
 
@@ -90,7 +103,7 @@ run_arch_tests() {
         fi
         
         # Build and run
-        if cargo xtask run --arch="$arch" 2>&1 | grep -qF "monolithic kernel exit [0] normally!"; then
+        if cargo xtask run --arch="$arch" --timeout "$RUN_TIMEOUT" 2>&1 | grep -qF "monolithic kernel exit [$PASS_EXIT_CODE] normally!"; then
             echo "✓ $arch test passed"
         else
             echo "Error: $arch test failed"
@@ -98,6 +111,15 @@ run_arch_tests() {
         fi
     done
     
+    # Kernel test configurations, run on riscv64 only
+    if command -v qemu-system-riscv64 &> /dev/null; then
+        # Reads each loaded segment back and panics if it differs or if any
+        # of its pages is mapped with other permissions than the segment's
+        run_riscv64_test --features verify-load
+        # Loads the payload from the initrd instead of the FAT disk
+        run_riscv64_test --boot-mode initrd
+    fi
+    
     if [ "$qemu_ok" = true ]; then
         echo ""
         echo "✓ All architecture tests passed"
@@ -105,6 +127,18 @@ run_arch_tests() {
     echo ""
 }
 
+# Run riscv64 with the extra `cargo xtask run` arguments "$@"
+run_riscv64_test() {
+    echo ""
+    echo "Testing riscv64 with $*"
+    if cargo xtask run --arch=riscv64 "$@" --timeout "$RUN_TIMEOUT" 2>&1 | grep -qF "monolithic kernel exit [$PASS_EXIT_CODE] normally!"; then
+        echo "✓ riscv64 ($*) test passed"
+    else
+        echo "Error: riscv64 ($*) test failed"
+        exit 1
+    fi
+}
+
 # Publish dry-run check by architecture
 check_publish() {
     echo "[6/7] Checking publish readiness..."
@@ -122,7 +156,7 @@ check_publish() {
         # Install config file for the architecture
         cp "configs/${arch}.toml" ".axconfig.toml"
         
-        if cargo publish --dry-run --features "axstd" --allow-dirty --target="$target" --registry crates-io; then
+        if cargo publish --dry-run --features "blk" --allow-dirty --target="$target" --registry crates-io; then
             echo "✓ $arch publish check passed"
         else
             echo "Error: $arch publish check failed"
@@ -146,7 +180,7 @@ print_summary() {
     echo "The following checks were performed:"
     echo "  1. Code format check (cargo fmt)"
     echo "  2. Lint check (cargo clippy)"
-    echo "  3. Basic build check (cargo check)"
+    echo "  3. Basic build check (cargo check) and xtask tests"
     echo "  4. Architecture tests (riscv64, x86_64, aarch64, loongarch64)"
     echo "  5. Publish readiness check (cargo publish --dry-run)"
     echo ""
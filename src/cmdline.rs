@@ -0,0 +1,62 @@
+use alloc::vec::Vec;
+
+/// Levels `log=` accepts; anything else would switch logging off.
+const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Settings taken from the kernel command line, which overrides the
+/// defaults built into the kernel.
+///
+/// The command line is `/chosen/bootargs` in the device tree, so it is only
+/// ever non-empty on platforms that boot with one.
+#[derive(Default)]
+pub struct BootArgs {
+    /// `log=<level>`: maximum log level, instead of `log-level` in the
+    /// axconfig file.
+    pub log_level: Option<&'static str>,
+    /// `init=<path>`: the payload to load.
+    pub init: Option<&'static str>,
+    /// Arguments that were not understood, kept to be reported by
+    /// [`BootArgs::warn_ignored`] once `log=` has taken effect.
+    ignored: Vec<&'static str>,
+}
+
+impl BootArgs {
+    /// Parse the command line the bootloader handed over, if any.
+    pub fn from_bootloader() -> Self {
+        axhal::dtb::get_chosen_bootargs().map_or_else(Self::default, Self::parse)
+    }
+
+    fn parse(cmdline: &'static str) -> Self {
+        let mut args = Self::default();
+        for arg in cmdline.split_ascii_whitespace() {
+            match arg.split_once('=') {
+                Some(("log", level)) if is_log_level(level) => args.log_level = Some(level),
+                Some(("init", path)) => args.init = Some(path),
+                // Read by axfs to pick the root filesystem.
+                Some(("root", _)) => {}
+                _ => args.ignored.push(arg),
+            }
+        }
+        args
+    }
+
+    /// Log the arguments [`BootArgs::parse`] ignored. Call it after setting
+    /// the log level, so `log=` applies to these warnings too.
+    pub fn warn_ignored(&self) {
+        for arg in &self.ignored {
+            match arg.split_once('=') {
+                Some(("log", level)) => warn!(
+                    "Ignoring log={}: not one of {}",
+                    level,
+                    LOG_LEVELS.join("/")
+                ),
+                _ => warn!("Ignoring unknown boot argument: {}", arg),
+            }
+        }
+    }
+}
+
+/// Whether `level` names one of [`LOG_LEVELS`], as `axlog` parses it.
+fn is_log_level(level: &str) -> bool {
+    LOG_LEVELS.iter().any(|l| l.eq_ignore_ascii_case(level))
+}
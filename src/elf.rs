@@ -0,0 +1,207 @@
+//! Just enough ELF64 parsing to find a static executable's entry point and
+//! `PT_LOAD` segments.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+const PT_LOAD: u32 = 1;
+/// Executable segment (`p_flags` bit).
+pub const PF_X: u32 = 1;
+/// Writable segment (`p_flags` bit).
+pub const PF_W: u32 = 2;
+/// Readable segment (`p_flags` bit).
+pub const PF_R: u32 = 4;
+
+/// `e_machine` of the architecture the kernel runs user code for.
+#[cfg(target_arch = "riscv64")]
+const EM_HOST: u16 = 243;
+#[cfg(target_arch = "aarch64")]
+const EM_HOST: u16 = 183;
+#[cfg(target_arch = "x86_64")]
+const EM_HOST: u16 = 62;
+#[cfg(target_arch = "loongarch64")]
+const EM_HOST: u16 = 258;
+
+/// Why an image was rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ElfError(&'static str);
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A `PT_LOAD` segment: `filesz` bytes at `offset` in the file, loaded at
+/// `vaddr` and zero-filled up to `memsz`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub vaddr: usize,
+    pub offset: usize,
+    pub filesz: usize,
+    pub memsz: usize,
+    pub flags: u32,
+}
+
+impl Segment {
+    /// The bytes of the segment taken from the file.
+    pub fn file_data<'a>(&self, image: &'a [u8]) -> &'a [u8] {
+        &image[self.offset..self.offset + self.filesz]
+    }
+}
+
+/// A parsed executable: where to start it and what to map.
+#[derive(Debug)]
+pub struct Elf {
+    pub entry: usize,
+    pub segments: Vec<Segment>,
+}
+
+/// The `N` bytes at `off`, if the image has them.
+fn bytes_at<const N: usize>(data: &[u8], off: usize) -> Result<[u8; N], ElfError> {
+    off.checked_add(N)
+        .and_then(|end| data.get(off..end))
+        .map(|bytes| bytes.try_into().unwrap())
+        .ok_or(ElfError("truncated"))
+}
+
+fn u16_at(data: &[u8], off: usize) -> Result<u16, ElfError> {
+    bytes_at(data, off).map(u16::from_le_bytes)
+}
+
+fn u32_at(data: &[u8], off: usize) -> Result<u32, ElfError> {
+    bytes_at(data, off).map(u32::from_le_bytes)
+}
+
+fn usize_at(data: &[u8], off: usize) -> Result<usize, ElfError> {
+    usize::try_from(u64::from_le_bytes(bytes_at(data, off)?))
+        .map_err(|_| ElfError("address out of range"))
+}
+
+/// Parse a little-endian ELF64 executable for this architecture.
+///
+/// Segments are loaded at their link addresses and no relocations are
+/// applied, so a position-independent image must not need any.
+pub fn parse(data: &[u8]) -> Result<Elf, ElfError> {
+    if data.get(..4) != Some(b"\x7fELF".as_slice()) {
+        return Err(ElfError("not an ELF file"));
+    }
+    if data.get(4..6) != Some([2u8, 1].as_slice()) {
+        return Err(ElfError("not little-endian ELF64"));
+    }
+    if u16_at(data, 18)? != EM_HOST {
+        return Err(ElfError("built for another architecture"));
+    }
+    let entry = usize_at(data, 24)?;
+    let phoff = usize_at(data, 32)?;
+    let phentsize = usize::from(u16_at(data, 54)?);
+    let phnum = usize::from(u16_at(data, 56)?);
+    if phentsize < 56 {
+        return Err(ElfError("bad program header size"));
+    }
+
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let ph = i
+            .checked_mul(phentsize)
+            .and_then(|off| off.checked_add(phoff))
+            .ok_or(ElfError("truncated"))?;
+        if u32_at(data, ph)? != PT_LOAD {
+            continue;
+        }
+        let segment = Segment {
+            flags: u32_at(data, ph + 4)?,
+            offset: usize_at(data, ph + 8)?,
+            vaddr: usize_at(data, ph + 16)?,
+            filesz: usize_at(data, ph + 32)?,
+            memsz: usize_at(data, ph + 40)?,
+        };
+        if segment.filesz > segment.memsz
+            || segment
+                .offset
+                .checked_add(segment.filesz)
+                .is_none_or(|end| end > data.len())
+            || segment.vaddr.checked_add(segment.memsz).is_none()
+        {
+            return Err(ElfError("segment out of bounds"));
+        }
+        if segment.memsz > 0 {
+            segments.push(segment);
+        }
+    }
+    if segments.is_empty() {
+        return Err(ElfError("no PT_LOAD segments"));
+    }
+    Ok(Elf { entry, segments })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ELF header plus one program header per `(type, flags, offset,
+    /// vaddr, filesz, memsz)`, followed by `payload`.
+    fn image(phdrs: &[(u32, u32, u64, u64, u64, u64)], payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+        data[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        data[18..20].copy_from_slice(&EM_HOST.to_le_bytes());
+        data[24..32].copy_from_slice(&0x1000u64.to_le_bytes());
+        data[32..40].copy_from_slice(&64u64.to_le_bytes());
+        data[54..56].copy_from_slice(&56u16.to_le_bytes());
+        data[56..58].copy_from_slice(&(phdrs.len() as u16).to_le_bytes());
+        for &(ty, flags, offset, vaddr, filesz, memsz) in phdrs {
+            let mut ph = [0u8; 56];
+            ph[0..4].copy_from_slice(&ty.to_le_bytes());
+            ph[4..8].copy_from_slice(&flags.to_le_bytes());
+            ph[8..16].copy_from_slice(&offset.to_le_bytes());
+            ph[16..24].copy_from_slice(&vaddr.to_le_bytes());
+            ph[32..40].copy_from_slice(&filesz.to_le_bytes());
+            ph[40..48].copy_from_slice(&memsz.to_le_bytes());
+            data.extend_from_slice(&ph);
+        }
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn load_segments_and_entry() {
+        let code = 64 + 3 * 56;
+        let data = image(
+            &[
+                (PT_LOAD, PF_R | PF_X, code, 0x1000, 4, 4),
+                (0x6474_e551, PF_R | PF_W, 0, 0, 0, 0),
+                (PT_LOAD, PF_R | PF_W, code + 4, 0x2000, 2, 0x100),
+            ],
+            &[1, 2, 3, 4, 5, 6],
+        );
+        let elf = parse(&data).unwrap();
+        assert_eq!(elf.entry, 0x1000);
+        assert_eq!(elf.segments.len(), 2);
+        assert_eq!(elf.segments[0].file_data(&data), &[1, 2, 3, 4]);
+        assert_eq!(elf.segments[1].vaddr, 0x2000);
+        assert_eq!(elf.segments[1].flags, PF_R | PF_W);
+        assert_eq!(elf.segments[1].file_data(&data), &[5, 6]);
+        assert_eq!(elf.segments[1].memsz, 0x100);
+    }
+
+    #[test]
+    fn reject_bad_images() {
+        assert_eq!(parse(b"\x00asm").unwrap_err(), ElfError("not an ELF file"));
+        let past_end = image(&[(PT_LOAD, PF_R, 64 + 56, 0x1000, 8, 8)], &[0; 4]);
+        assert_eq!(
+            parse(&past_end).unwrap_err(),
+            ElfError("segment out of bounds")
+        );
+        let no_load = image(&[], &[]);
+        assert_eq!(
+            parse(&no_load).unwrap_err(),
+            ElfError("no PT_LOAD segments")
+        );
+        let mut other_arch = image(&[(PT_LOAD, PF_R, 0, 0x1000, 0, 8)], &[]);
+        other_arch[18] ^= 0xff;
+        assert_eq!(
+            parse(&other_arch).unwrap_err(),
+            ElfError("built for another architecture")
+        );
+    }
+}
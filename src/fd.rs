@@ -0,0 +1,290 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng_vfs::{Location, Metadata};
+use axsync::Mutex;
+
+/// File type bits of `st_mode` for a character device.
+const S_IFCHR: u32 = 0o020000;
+/// Preferred I/O block size reported for the console.
+const STDIO_BLKSIZE: u32 = 4096;
+/// Exclusive upper bound on the fd numbers `dup3` may target.
+const FD_LIMIT: usize = 1024;
+
+/// Console input that [`console_readable`] pulled from the device to see if
+/// any was pending, kept until [`console_read`] hands it to user space.
+static CONSOLE_LOOKAHEAD: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Whether a `read` from the console would return data right now.
+pub fn console_readable() -> bool {
+    let mut pending = CONSOLE_LOOKAHEAD.lock();
+    if pending.is_empty() {
+        let mut byte = [0u8];
+        if axhal::console::read_bytes(&mut byte) == 1 {
+            pending.push(byte[0]);
+        }
+    }
+    !pending.is_empty()
+}
+
+/// Read available console input into `buf` without blocking.
+pub fn console_read(buf: &mut [u8]) -> usize {
+    let mut pending = CONSOLE_LOOKAHEAD.lock();
+    let n = pending.len().min(buf.len());
+    buf[..n].copy_from_slice(&pending[..n]);
+    pending.drain(..n);
+    n + axhal::console::read_bytes(&mut buf[n..])
+}
+
+/// An object a file descriptor refers to. Cloning it, as `dup` does, shares
+/// the underlying open file and its offset.
+#[derive(Clone)]
+pub enum FileLike {
+    /// The console, behind the standard streams 0, 1 and 2.
+    Stdio,
+    /// A regular file opened through `axfs`.
+    File(Arc<OpenFile>),
+    /// A directory opened through `axfs`.
+    Dir(Location),
+}
+
+impl FileLike {
+    /// Describe the object in the generic `struct stat` layout.
+    pub fn stat(&self) -> LinuxResult<Kstat> {
+        match self {
+            Self::Stdio => Ok(Kstat {
+                st_mode: S_IFCHR | 0o620,
+                st_nlink: 1,
+                st_blksize: STDIO_BLKSIZE as i32,
+                ..Default::default()
+            }),
+            Self::File(file) => Ok(Kstat::from_metadata(&file.file.location().metadata()?)),
+            Self::Dir(loc) => Ok(Kstat::from_metadata(&loc.metadata()?)),
+        }
+    }
+}
+
+/// An open regular file together with the offset `read`, `write` and
+/// `lseek` operate on.
+pub struct OpenFile {
+    pub file: axfs::File,
+    pub offset: Mutex<u64>,
+}
+
+impl OpenFile {
+    pub fn new(file: axfs::File) -> Self {
+        Self {
+            file,
+            offset: Mutex::new(0),
+        }
+    }
+
+    /// Current size of the file in bytes.
+    pub fn size(&self) -> LinuxResult<u64> {
+        Ok(self.file.location().len()?)
+    }
+}
+
+/// Per-process file descriptor table, indexed by fd number.
+pub struct FdTable {
+    files: Vec<Option<FileLike>>,
+}
+
+impl FdTable {
+    /// A table with stdin, stdout and stderr open on the console.
+    pub fn with_stdio() -> Self {
+        Self {
+            files: vec![
+                Some(FileLike::Stdio),
+                Some(FileLike::Stdio),
+                Some(FileLike::Stdio),
+            ],
+        }
+    }
+
+    /// Look up `fd`, or fail with `-EBADF` if it is not open.
+    pub fn get(&self, fd: usize) -> LinuxResult<&FileLike> {
+        self.files
+            .get(fd)
+            .and_then(Option::as_ref)
+            .ok_or(LinuxError::EBADF)
+    }
+
+    /// Install `file` at the lowest free fd and return that fd.
+    pub fn add(&mut self, file: FileLike) -> usize {
+        match self.files.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.files[fd] = Some(file);
+                fd
+            }
+            None => {
+                self.files.push(Some(file));
+                self.files.len() - 1
+            }
+        }
+    }
+
+    /// Install a copy of `fd` at the lowest free fd and return that fd.
+    pub fn dup(&mut self, fd: usize) -> LinuxResult<usize> {
+        let file = self.get(fd)?.clone();
+        Ok(self.add(file))
+    }
+
+    /// Make `newfd` refer to the same file as `oldfd`, closing whatever
+    /// `newfd` referred to first.
+    pub fn dup_to(&mut self, oldfd: usize, newfd: usize) -> LinuxResult {
+        let file = self.get(oldfd)?.clone();
+        if newfd >= FD_LIMIT {
+            return Err(LinuxError::EBADF);
+        }
+        if newfd >= self.files.len() {
+            self.files.resize(newfd + 1, None);
+        }
+        self.files[newfd] = Some(file);
+        Ok(())
+    }
+
+    /// Close `fd`, or fail with `-EBADF` if it is not open.
+    pub fn remove(&mut self, fd: usize) -> LinuxResult {
+        self.files
+            .get_mut(fd)
+            .and_then(Option::take)
+            .map(drop)
+            .ok_or(LinuxError::EBADF)
+    }
+}
+
+/// `struct stat` as laid out by the generic (asm-generic) Linux ABI, which
+/// matches the generic syscall numbering used on every architecture here.
+#[repr(C)]
+#[derive(Default)]
+pub struct Kstat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    __pad1: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    __pad2: i32,
+    pub st_blocks: i64,
+    pub st_atime: i64,
+    pub st_atime_nsec: u64,
+    pub st_mtime: i64,
+    pub st_mtime_nsec: u64,
+    pub st_ctime: i64,
+    pub st_ctime_nsec: u64,
+    __unused: [u32; 2],
+}
+
+impl Kstat {
+    pub fn from_metadata(meta: &Metadata) -> Self {
+        Self {
+            st_dev: meta.device,
+            st_ino: meta.inode,
+            // `NodeType` discriminants are the `S_IFMT` bits shifted right by 12.
+            st_mode: ((meta.node_type as u32) << 12) | meta.mode.bits() as u32,
+            st_nlink: meta.nlink as u32,
+            st_uid: meta.uid,
+            st_gid: meta.gid,
+            st_size: meta.size as i64,
+            st_blksize: meta.block_size as i32,
+            st_blocks: meta.blocks as i64,
+            st_atime: meta.atime.as_secs() as i64,
+            st_atime_nsec: meta.atime.subsec_nanos() as u64,
+            st_mtime: meta.mtime.as_secs() as i64,
+            st_mtime_nsec: meta.mtime.subsec_nanos() as u64,
+            st_ctime: meta.ctime.as_secs() as i64,
+            st_ctime_nsec: meta.ctime.subsec_nanos() as u64,
+            ..Default::default()
+        }
+    }
+
+    /// Raw bytes of the structure, ready to be copied to user space.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// `struct statx_timestamp`.
+#[repr(C)]
+#[derive(Default)]
+struct StatxTimestamp {
+    tv_sec: i64,
+    tv_nsec: u32,
+    __reserved: i32,
+}
+
+impl StatxTimestamp {
+    fn new(sec: i64, nsec: u64) -> Self {
+        Self {
+            tv_sec: sec,
+            tv_nsec: nsec as u32,
+            __reserved: 0,
+        }
+    }
+}
+
+/// `struct statx`, identical on every architecture.
+#[repr(C)]
+#[derive(Default)]
+pub struct Statx {
+    stx_mask: u32,
+    stx_blksize: u32,
+    stx_attributes: u64,
+    stx_nlink: u32,
+    stx_uid: u32,
+    stx_gid: u32,
+    stx_mode: u16,
+    __spare0: u16,
+    stx_ino: u64,
+    stx_size: u64,
+    stx_blocks: u64,
+    stx_attributes_mask: u64,
+    stx_atime: StatxTimestamp,
+    stx_btime: StatxTimestamp,
+    stx_ctime: StatxTimestamp,
+    stx_mtime: StatxTimestamp,
+    stx_rdev_major: u32,
+    stx_rdev_minor: u32,
+    stx_dev_major: u32,
+    stx_dev_minor: u32,
+    __spare2: [u64; 14],
+}
+
+/// `STATX_BASIC_STATS`: every field also present in `struct stat`.
+const STATX_BASIC_STATS: u32 = 0x7ff;
+
+impl From<&Kstat> for Statx {
+    fn from(st: &Kstat) -> Self {
+        Self {
+            stx_mask: STATX_BASIC_STATS,
+            stx_blksize: st.st_blksize as u32,
+            stx_nlink: st.st_nlink,
+            stx_uid: st.st_uid,
+            stx_gid: st.st_gid,
+            stx_mode: st.st_mode as u16,
+            stx_ino: st.st_ino,
+            stx_size: st.st_size as u64,
+            stx_blocks: st.st_blocks as u64,
+            stx_atime: StatxTimestamp::new(st.st_atime, st.st_atime_nsec),
+            stx_ctime: StatxTimestamp::new(st.st_ctime, st.st_ctime_nsec),
+            stx_mtime: StatxTimestamp::new(st.st_mtime, st.st_mtime_nsec),
+            stx_dev_major: (st.st_dev >> 8) as u32,
+            stx_dev_minor: (st.st_dev & 0xff) as u32,
+            ..Default::default()
+        }
+    }
+}
+
+impl Statx {
+    /// Raw bytes of the structure, ready to be copied to user space.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
@@ -1,37 +1,275 @@
+use alloc::vec::Vec;
+
+#[cfg(feature = "initrd")]
+use axalloc::UsageKind;
+#[cfg(not(feature = "initrd"))]
 use axfs::ROOT_FS_CONTEXT;
+#[cfg(feature = "initrd")]
+use axhal::mem::{MemRegionFlags, PhysAddr, PhysMemRegion, memory_regions};
+use axhal::mem::{PAGE_SIZE_4K, phys_to_virt};
 use axhal::paging::MappingFlags;
+#[cfg(not(feature = "initrd"))]
+use axio::Read;
 use axmm::AddrSpace;
+use memory_addr::{MemoryAddr, PageIter4K, VirtAddr};
+
+use crate::elf;
+
+/// Map the ELF executable `data`, read from `fname` by [`load_file`], into
+/// `uspace` and return its entry point.
+///
+/// Each `PT_LOAD` segment gets its own eagerly allocated, zeroed pages with
+/// the permissions it asks for, so code is never writable and `.data` and
+/// `.bss` are never executable.
+pub fn load_user_app(
+    fname: &str,
+    data: &[u8],
+    uspace: &mut AddrSpace,
+) -> Result<VirtAddr, axio::Error> {
+    let image = elf::parse(data).map_err(|e| {
+        error!("Cannot load {}: {}", fname, e);
+        axio::Error::InvalidData
+    })?;
+    let mut entry_mapped = false;
+    for segment in &image.segments {
+        let flags = segment_flags(segment.flags)?;
+        let start = VirtAddr::from(segment.vaddr).align_down_4k();
+        let end = VirtAddr::from(segment.vaddr + segment.memsz).align_up_4k();
+        map_anonymous(uspace, start, end - start, flags)?;
+
+        // Write the file contents through the kernel's linear mapping of
+        // each frame, which stays writable whatever the user flags are.
+        let file_data = segment.file_data(data);
+        uspace
+            .write(segment.vaddr.into(), file_data)
+            .map_err(|_| axio::Error::NoMemory)?;
+        #[cfg(feature = "verify-load")]
+        verify_loaded(uspace, segment.vaddr.into(), file_data, flags);
 
-use crate::APP_ENTRY;
+        debug!("Segment [{:#x}, {:#x}) {:?}", start, end, flags);
+        entry_mapped |= flags.contains(MappingFlags::EXECUTE)
+            && (segment.vaddr..segment.vaddr + segment.memsz).contains(&image.entry);
+    }
+    if !entry_mapped {
+        error!(
+            "Cannot load {}: entry {:#x} is not in an executable segment",
+            fname, image.entry
+        );
+        return Err(axio::Error::InvalidData);
+    }
 
-pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> Result<(), axio::Error> {
-    let mut buf = [0u8; axhal::mem::PAGE_SIZE_4K];
-    let n = load_file(fname, &mut buf)?;
+    info!(
+        "Loaded app {} ({} bytes, {} segments), entry {:#x}",
+        fname,
+        data.len(),
+        image.segments.len(),
+        image.entry
+    );
+    Ok(image.entry.into())
+}
 
-    // Map user code with eager allocation (populate=true)
-    uspace
-        .map_alloc(
-            (APP_ENTRY).into(),
-            axhal::mem::PAGE_SIZE_4K,
-            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE | MappingFlags::USER,
-            true, // populate=true: allocate immediately
-        )
-        .map_err(|_| axio::Error::NoMemory)?;
+/// User mapping flags for an ELF segment's `p_flags`. Writable and
+/// executable at once is refused with `PermissionDenied` unless the
+/// `allow-wx` feature is enabled, as `mprotect` does.
+fn segment_flags(p_flags: u32) -> Result<MappingFlags, axio::Error> {
+    let mut flags = MappingFlags::USER;
+    for (bit, flag) in [
+        (elf::PF_R, MappingFlags::READ),
+        (elf::PF_W, MappingFlags::WRITE),
+        (elf::PF_X, MappingFlags::EXECUTE),
+    ] {
+        if p_flags & bit != 0 {
+            flags |= flag;
+        }
+    }
+    if flags.contains(MappingFlags::WRITE | MappingFlags::EXECUTE) && !cfg!(feature = "allow-wx") {
+        error!("Refusing a writable and executable segment");
+        return Err(axio::Error::PermissionDenied);
+    }
+    Ok(flags)
+}
 
-    // Write the loaded data into the address space
-    uspace
-        .write((APP_ENTRY).into(), &buf[..n])
-        .map_err(|_| axio::Error::NoMemory)?;
+/// Map an eagerly allocated anonymous region and zero-fill every page.
+///
+/// The frames are cleared through the kernel's linear mapping before user
+/// space can reach them, so stale kernel data never leaks and `.bss`-like
+/// memory is guaranteed to read as zero regardless of the backend's policy.
+///
+/// Fails with `AlreadyExists` if the region overlaps an existing mapping.
+pub fn map_anonymous(
+    uspace: &mut AddrSpace,
+    start: VirtAddr,
+    size: usize,
+    flags: MappingFlags,
+) -> Result<(), axio::Error> {
+    check_unmapped(uspace, start, size)?;
+    uspace.map_alloc(start, size, flags, true)?; // populate=true: allocate immediately
 
-    ax_println!("Loaded app {} ({} bytes) at {:#x}", fname, n, APP_ENTRY);
+    for vaddr in PageIter4K::new(start, start + size).ok_or(axio::Error::InvalidInput)? {
+        let (paddr, _, _) = uspace
+            .page_table()
+            .query(vaddr)
+            .map_err(|_| axio::Error::BadAddress)?;
+        unsafe { core::ptr::write_bytes(phys_to_virt(paddr).as_mut_ptr(), 0, PAGE_SIZE_4K) };
+    }
+    Ok(())
+}
 
+/// Fail with `AlreadyExists` if any page of `[start, start + size)` is
+/// already mapped in `uspace`, logging both the requested range and the span
+/// of the mapped pages it collides with.
+fn check_unmapped(uspace: &AddrSpace, start: VirtAddr, size: usize) -> Result<(), axio::Error> {
+    let end = start
+        .as_usize()
+        .checked_add(size)
+        .ok_or(axio::Error::InvalidInput)?;
+    let mut mapped = PageIter4K::new(start, end.into())
+        .ok_or(axio::Error::InvalidInput)?
+        .filter(|&vaddr| uspace.page_table().query(vaddr).is_ok());
+    if let Some(first) = mapped.next() {
+        let last = mapped.last().unwrap_or(first);
+        error!(
+            "Cannot map [{:#x}, {:#x}): overlaps mapped pages in [{:#x}, {:#x})",
+            start,
+            end,
+            first,
+            last + PAGE_SIZE_4K
+        );
+        return Err(axio::Error::AlreadyExists);
+    }
     Ok(())
 }
 
-fn load_file(fname: &str, buf: &mut [u8]) -> Result<usize, axio::Error> {
-    ax_println!("app: {}", fname);
+/// Re-read `data` through the user page table at `start` and panic if what
+/// landed in the frames differs from the source buffer, or if any of its
+/// pages is not mapped with exactly the segment's `flags`.
+#[cfg(feature = "verify-load")]
+fn verify_loaded(uspace: &AddrSpace, start: VirtAddr, data: &[u8], flags: MappingFlags) {
+    let perms =
+        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE | MappingFlags::USER;
+    let mut off = 0;
+    while off < data.len() {
+        let vaddr = start + off;
+        let page = vaddr.align_down_4k();
+        let (frame, mapped_flags, _) = uspace
+            .page_table()
+            .query(page)
+            .unwrap_or_else(|e| panic!("verify-load: {:#x} is not mapped: {:?}", vaddr, e));
+        if mapped_flags & perms != flags {
+            panic!(
+                "verify-load: page {:#x} is mapped {:?}, its segment wants {:?}",
+                page, mapped_flags, flags
+            );
+        }
+        let len = (page + PAGE_SIZE_4K - vaddr).min(data.len() - off);
+        let paddr = frame + (vaddr - page);
+        let mapped = unsafe { core::slice::from_raw_parts(phys_to_virt(paddr).as_ptr(), len) };
+        let chunk = &data[off..off + len];
+        if let Some(i) = mapped.iter().zip(chunk).position(|(a, b)| a != b) {
+            panic!(
+                "verify-load: mismatch at {:#x} (paddr {:#x}): expected {:#04x}, found {:#04x}",
+                vaddr + i,
+                paddr + i,
+                chunk[i],
+                mapped[i]
+            );
+        }
+        off += len;
+    }
+    debug!("verify-load: {} bytes at {:#x} match", data.len(), start);
+}
+
+/// Read the app image `fname` from the root filesystem.
+#[cfg(not(feature = "initrd"))]
+pub fn load_file(fname: &str) -> Result<Vec<u8>, axio::Error> {
+    debug!("app: {}", fname);
     let ctx = ROOT_FS_CONTEXT.get().expect("Root FS not initialized");
     let file = axfs::File::open(ctx, fname).map_err(|_| axio::Error::NotFound)?;
-    let n = file.read(buf)?;
-    Ok(n)
+    let mut data = Vec::new();
+    (&file).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// With the `initrd` feature the whole initrd is the app image, wherever
+/// the bootloader placed it according to `/chosen` in the device tree.
+///
+/// The platform does not reserve the initrd, so its frames are taken from
+/// the page allocator for the duration of the copy (see [`reserve_initrd`]);
+/// `main` calls this before creating the user address space.
+#[cfg(feature = "initrd")]
+pub fn load_file(fname: &str) -> Result<Vec<u8>, axio::Error> {
+    debug!("app: {} (from initrd)", fname);
+    let chosen = axhal::dtb::get_fdt()
+        .and_then(|fdt| fdt.find_nodes("/chosen").next())
+        .ok_or(axio::Error::NotFound)?;
+    let cell = |name| {
+        let prop = chosen.find_property(name)?;
+        let raw = prop.raw_value();
+        match raw.len() {
+            4 => Some(u32::from_be_bytes(raw.try_into().unwrap()) as usize),
+            8 => Some(u64::from_be_bytes(raw.try_into().unwrap()) as usize),
+            _ => None,
+        }
+    };
+    let start = cell("linux,initrd-start").ok_or(axio::Error::NotFound)?;
+    let end = cell("linux,initrd-end").ok_or(axio::Error::NotFound)?;
+    if end <= start {
+        return Err(axio::Error::InvalidData);
+    }
+    debug!("initrd: [{:#x}, {:#x})", start, end);
+    let (frames, num_pages) = reserve_initrd(start.into(), end.into())?;
+    let initrd =
+        unsafe { core::slice::from_raw_parts(phys_to_virt(start.into()).as_ptr(), end - start) };
+    let data = initrd.to_vec();
+    axalloc::global_allocator().dealloc_pages(frames.as_usize(), num_pages, UsageKind::Global);
+    Ok(data)
+}
+
+/// Take the frames under the initrd `[start, end)` from the page allocator,
+/// returning their virtual address and count.
+///
+/// Fails with `InvalidData` if the initrd is not in the free memory the page
+/// allocator manages, and with `ResourceBusy` if some of its frames were
+/// handed out during boot, in which case it may have been overwritten.
+#[cfg(feature = "initrd")]
+fn reserve_initrd(start: PhysAddr, end: PhysAddr) -> Result<(VirtAddr, usize), axio::Error> {
+    let (start, end) = (start.align_down_4k(), end.align_up_4k());
+    let region = page_allocator_region().ok_or(axio::Error::InvalidData)?;
+    if start < region.paddr || end > region.paddr + region.size {
+        error!(
+            "initrd [{:#x}, {:#x}) is outside the allocator's free memory [{:#x}, {:#x})",
+            start,
+            end,
+            region.paddr,
+            region.paddr + region.size
+        );
+        return Err(axio::Error::InvalidData);
+    }
+    let num_pages = (end - start) / PAGE_SIZE_4K;
+    let vaddr = phys_to_virt(start);
+    axalloc::global_allocator()
+        .alloc_pages_at(vaddr.as_usize(), num_pages, PAGE_SIZE_4K, UsageKind::Global)
+        .map_err(|_| {
+            error!(
+                "initrd [{:#x}, {:#x}) overlaps frames allocated during boot",
+                start, end
+            );
+            axio::Error::ResourceBusy
+        })?;
+    Ok((vaddr, num_pages))
+}
+
+/// The free memory region axruntime gives the page allocator: the first one
+/// after the kernel's `.bss`, or else the largest.
+#[cfg(feature = "initrd")]
+fn page_allocator_region() -> Option<PhysMemRegion> {
+    let free = |r: &PhysMemRegion| r.flags.contains(MemRegionFlags::FREE);
+    memory_regions()
+        .skip_while(|r| r.name != ".bss")
+        .find(free)
+        .or_else(|| {
+            memory_regions()
+                .filter(free)
+                .reduce(|a, b| if b.size > a.size { b } else { a })
+        })
 }
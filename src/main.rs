@@ -4,7 +4,7 @@
 #[cfg(feature = "axstd")]
 extern crate axstd as std;
 
-#[cfg(feature = "axstd")]
+#[cfg(any(feature = "axstd", test))]
 extern crate alloc;
 
 #[cfg(feature = "axstd")]
@@ -16,61 +16,120 @@ extern crate axfs;
 #[cfg(feature = "axstd")]
 extern crate axio;
 
+// `axstd` alone has no way to load the payload.
+#[cfg(all(feature = "axstd", not(any(feature = "blk", feature = "initrd"))))]
+compile_error!("enable a boot mode: the `blk` or `initrd` feature");
+
+#[cfg(feature = "axstd")]
+mod cmdline;
+#[cfg(any(feature = "axstd", test))]
+mod elf;
+#[cfg(feature = "axstd")]
+mod fd;
 #[cfg(feature = "axstd")]
 mod loader;
 #[cfg(feature = "axstd")]
 mod syscall;
 #[cfg(feature = "axstd")]
 mod task;
+#[cfg(any(feature = "axstd", test))]
+mod ustack;
 
 #[cfg(feature = "axstd")]
 const USER_STACK_SIZE: usize = 0x10000;
 #[cfg(feature = "axstd")]
 const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
+/// Where the payload is linked to start (see payload/linker.ld).
 #[cfg(feature = "axstd")]
 const APP_ENTRY: usize = 0x1000;
+#[cfg(feature = "axstd")]
+const APP_PATH: &str = "/sbin/origin";
+/// How often `main` looks for the payload spinning on its release word.
+#[cfg(feature = "axstd")]
+const PREEMPT_CHECK_DELAY: core::time::Duration = core::time::Duration::from_millis(100);
+/// Value the payload stores in its release word once it starts spinning.
+#[cfg(feature = "axstd")]
+const PAYLOAD_SPINNING: u32 = 1;
+/// Value `main` stores in the release word to let the payload go on.
+#[cfg(feature = "axstd")]
+const PAYLOAD_RELEASED: u32 = 2;
 
 #[cfg_attr(feature = "axstd", unsafe(no_mangle))]
 fn main() {
     #[cfg(feature = "axstd")]
     {
         use axhal::paging::MappingFlags;
-        use memory_addr::va;
 
-        // A new address space for user app using axmm::new_user_aspace().
-        // User space: [0x0, 0x40_0000_0000) — 256GB, below kernel space.
-        let mut uspace = axmm::new_user_aspace(va!(0x0), 0x40_0000_0000).unwrap();
+        // Log level from `log=` on the kernel command line, or else from
+        // `log-level` in the axconfig file (see build.rs).
+        let boot_args = cmdline::BootArgs::from_bootloader();
+        axlog::set_max_level(boot_args.log_level.unwrap_or(env!("APP_LOG_LEVEL")));
+        boot_args.warn_ignored();
+        let app_path = boot_args.init.unwrap_or(APP_PATH);
+
+        // Read the user app first: with `initrd` this copies the initrd out
+        // before page-table frames get allocated (see `loader::load_file`).
+        let image =
+            loader::load_file(app_path).unwrap_or_else(|e| panic!("Cannot load app! {:?}", e));
+
+        // A new address space for the user app over the configured user
+        // range, [0x0, 0x40_0000_0000) by default: 256GB below kernel space.
+        let mut uspace = task::new_user_aspace().unwrap();
 
         // Load user app binary file into address space.
-        if let Err(e) = loader::load_user_app("/sbin/origin", &mut uspace) {
-            panic!("Cannot load app! {:?}", e);
-        }
+        let entry = loader::load_user_app(app_path, &image, &mut uspace)
+            .unwrap_or_else(|e| panic!("Cannot load app! {:?}", e));
+        drop(image);
 
-        // Init user stack with eager allocation.
+        // Init user stack with eager, zero-filled allocation.
         let ustack_top = uspace.end();
         let ustack_vaddr = ustack_top - USER_STACK_SIZE;
-        ax_println!(
+        debug!(
             "Mapping user stack: {:#x?} -> {:#x?}",
-            ustack_vaddr,
-            ustack_top
+            ustack_vaddr, ustack_top
         );
-        uspace
-            .map_alloc(
-                ustack_vaddr,
-                USER_STACK_SIZE,
-                MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-                true, // populate=true: allocate immediately
-            )
-            .unwrap();
+        loader::map_anonymous(
+            &mut uspace,
+            ustack_vaddr,
+            USER_STACK_SIZE,
+            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+        )
+        .unwrap_or_else(|e| panic!("Cannot map user stack! {:?}", e));
 
-        ax_println!("New user address space: {:#x?}", uspace);
+        debug!("New user address space: {:#x?}", uspace);
 
         // Let's kick off the user process.
-        let user_task = task::spawn_user_task(uspace, ustack_top);
+        // The payload gets the address of a zeroed word at the bottom of its
+        // stack, which it spins on until this task releases it.
+        let release = ustack_vaddr;
+        let (user_task, proc) =
+            match task::spawn_user_task(uspace, entry, ustack_top, app_path, release.as_usize()) {
+                Ok(spawned) => spawned,
+                Err(e) => panic!("Cannot spawn user task! {:?}", e),
+            };
+
+        // Preemption check: with a single CPU, this task only sees the
+        // payload spinning if a timer interrupt preempts the spin.
+        let pid = proc.lock().pid;
+        while task::process_exists(pid) {
+            axtask::sleep(PREEMPT_CHECK_DELAY);
+            let aspace = &proc.lock().aspace;
+            let mut word = [0u8; 4];
+            if let Err(e) = aspace.read(release, &mut word) {
+                warn!("Cannot read the payload's release word! {:?}", e);
+                break;
+            }
+            if u32::from_ne_bytes(word) == PAYLOAD_SPINNING {
+                if let Err(e) = aspace.write(release, &PAYLOAD_RELEASED.to_ne_bytes()) {
+                    warn!("Cannot release the payload! {:?}", e);
+                }
+                break;
+            }
+        }
 
         // Wait for user process to exit ...
         let exit_code = user_task.join();
-        ax_println!("monolithic kernel exit [{:?}] normally!", exit_code);
+        info!("monolithic kernel exit [{:?}] normally!", exit_code);
     }
     #[cfg(not(feature = "axstd"))]
     {
@@ -1,6 +1,117 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axfs::{FileFlags, FsContext, OpenOptions, OpenResult, ROOT_FS_CONTEXT};
+use axhal::mem::PAGE_SIZE_4K;
+use axhal::paging::MappingFlags;
 use axhal::uspace::UserContext;
+use axmm::AddrSpace;
+use axsync::Mutex;
+use memory_addr::{MemoryAddr, PageIter4K, VirtAddr, is_aligned_4k};
+
+use crate::fd::{self, FileLike, Kstat, OpenFile, Statx};
+use crate::task::{self, ProcessState};
 
+const SYS_GETCWD: usize = 17;
+const SYS_DUP: usize = 23;
+const SYS_DUP3: usize = 24;
+const SYS_IOCTL: usize = 29;
+const SYS_CHDIR: usize = 49;
+const SYS_OPENAT: usize = 56;
+const SYS_CLOSE: usize = 57;
+const SYS_LSEEK: usize = 62;
+const SYS_READ: usize = 63;
+const SYS_WRITE: usize = 64;
+const SYS_PPOLL: usize = 73;
+const SYS_READLINKAT: usize = 78;
+const SYS_FSTAT: usize = 80;
 const SYS_EXIT: usize = 93;
+const SYS_EXIT_GROUP: usize = 94;
+const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_SET_ROBUST_LIST: usize = 99;
+const SYS_KILL: usize = 129;
+const SYS_SIGALTSTACK: usize = 132;
+const SYS_RT_SIGPROCMASK: usize = 135;
+const SYS_GETPID: usize = 172;
+const SYS_GETTID: usize = 178;
+const SYS_MPROTECT: usize = 226;
+const SYS_GETRANDOM: usize = 278;
+const SYS_STATX: usize = 291;
+
+/// `dirfd` value meaning "relative to the current working directory".
+const AT_FDCWD: isize = -100;
+/// `statx` flag: operate on `dirfd` itself when the path is empty.
+const AT_EMPTY_PATH: usize = 0x1000;
+
+// `openat` flags; these values are shared by every supported architecture.
+const O_ACCMODE: usize = 0o3;
+const O_WRONLY: usize = 0o1;
+const O_RDWR: usize = 0o2;
+const O_CREAT: usize = 0o100;
+const O_EXCL: usize = 0o200;
+const O_TRUNC: usize = 0o1000;
+const O_APPEND: usize = 0o2000;
+const O_CLOEXEC: usize = 0o2000000;
+
+// `lseek` whence values.
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const SEEK_END: usize = 2;
+
+/// Most bytes moved by a single `read` or `write`; larger requests are
+/// completed short, as POSIX allows.
+const IO_CHUNK: usize = 0x1_0000;
+
+// `poll` event bits.
+const POLLIN: i16 = 0x1;
+const POLLOUT: i16 = 0x4;
+const POLLNVAL: i16 = 0x20;
+
+/// Most `pollfd` entries accepted by one `ppoll`.
+const POLL_MAX_FDS: usize = 1024;
+/// How often `ppoll` re-checks the console while waiting for input.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// `mprotect` protection bits.
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const PROT_EXEC: usize = 0x4;
+
+// Signals `kill` can deliver.
+const SIGKILL: i32 = 9;
+const SIGTERM: i32 = 15;
+/// Highest valid signal number.
+const SIGMAX: i32 = 64;
+/// Size of the kernel's `sigset_t`, one bit per signal.
+const SIGSET_SIZE: usize = 8;
+/// `stack_t::ss_flags`: no alternate signal stack is in use.
+const SS_DISABLE: u32 = 2;
+
+// Terminal `ioctl` requests.
+const TCGETS: usize = 0x5401;
+const TIOCGWINSZ: usize = 0x5413;
+/// Window size reported for the console, as rows and columns.
+const CONSOLE_WINSIZE: (u16, u16) = (25, 80);
+
+/// State of the `getrandom` generator; 0 until first seeded.
+static RNG_STATE: Mutex<u64> = Mutex::new(0);
+
+/// Longest path accepted from user space, including the terminating NUL.
+const PATH_MAX: usize = 4096;
+
+/// What the trap loop should do after a syscall has been handled.
+pub enum SyscallOutcome {
+    /// Return to user space.
+    Continue,
+    /// Terminate the calling task only.
+    Exit(i32),
+    /// Terminate every task sharing the caller's address space.
+    ExitGroup(i32),
+}
 
 /// Get the syscall number from the UserContext (architecture-specific register).
 fn syscall_num(uctx: &UserContext) -> usize {
@@ -23,22 +134,641 @@ fn syscall_num(uctx: &UserContext) -> usize {
 }
 
 /// Handle a syscall from user space.
-/// Returns `Some(exit_code)` if the user process wants to exit,
-/// or `None` to continue running.
-pub fn handle_syscall(uctx: &mut UserContext) -> Option<i32> {
-    ax_println!("handle_syscall ...");
-
+/// Returns a [`SyscallOutcome`] telling the trap loop whether to resume the
+/// user context or to tear down the calling task (or its whole group).
+///
+/// `process` is locked for the whole of most syscalls, but syscalls that may
+/// wait release it while they sleep.
+pub fn handle_syscall(uctx: &mut UserContext, process: &Mutex<ProcessState>) -> SyscallOutcome {
     let num = syscall_num(uctx);
-    match num {
+    trace!("handle_syscall {} ...", num);
+
+    let ret = match num {
         SYS_EXIT => {
-            ax_println!("[SYS_EXIT]: process is exiting ..");
-            let exit_code = uctx.arg0() as i32;
-            Some(exit_code)
+            info!("[SYS_EXIT]: process is exiting ..");
+            return SyscallOutcome::Exit(uctx.arg0() as i32);
+        }
+        SYS_EXIT_GROUP => {
+            info!("[SYS_EXIT_GROUP]: process group is exiting ..");
+            return SyscallOutcome::ExitGroup(uctx.arg0() as i32);
+        }
+        SYS_READ => sys_read(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        SYS_PPOLL => sys_ppoll(process, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        _ => dispatch(uctx, num, &mut process.lock()),
+    };
+    uctx.set_retval(match ret {
+        Ok(val) => val,
+        Err(e) => -(e.code() as isize) as usize,
+    });
+    SyscallOutcome::Continue
+}
+
+/// Handle a syscall that never waits, with the process locked throughout.
+fn dispatch(uctx: &UserContext, num: usize, proc: &mut ProcessState) -> LinuxResult<usize> {
+    match num {
+        SYS_GETCWD => sys_getcwd(proc, uctx.arg0(), uctx.arg1()),
+        SYS_CHDIR => sys_chdir(proc, uctx.arg0()),
+        SYS_DUP => proc.fds.dup(uctx.arg0()),
+        SYS_DUP3 => sys_dup3(proc, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        SYS_IOCTL => sys_ioctl(proc, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        SYS_OPENAT => sys_openat(proc, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg3()),
+        SYS_CLOSE => proc.fds.remove(uctx.arg0()).map(|_| 0),
+        SYS_LSEEK => sys_lseek(proc, uctx.arg0(), uctx.arg1() as isize, uctx.arg2()),
+        SYS_WRITE => sys_write(proc, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        SYS_READLINKAT => sys_readlinkat(proc, uctx.arg1(), uctx.arg2(), uctx.arg3()),
+        SYS_FSTAT => sys_fstat(proc, uctx.arg0(), uctx.arg1()),
+        SYS_STATX => sys_statx(proc, uctx.arg0(), uctx.arg1(), uctx.arg2(), uctx.arg4()),
+        SYS_SET_TID_ADDRESS => {
+            proc.clear_child_tid = uctx.arg0();
+            Ok(proc.pid)
+        }
+        // Processes are single-threaded, so the thread ID is the process ID.
+        SYS_GETPID | SYS_GETTID => Ok(proc.pid),
+        // Startup calls of C runtimes, harmless to ignore while there are
+        // no threads and no signal handlers.
+        SYS_SET_ROBUST_LIST => {
+            trace!("[SYS_SET_ROBUST_LIST]: ignored");
+            Ok(0)
+        }
+        SYS_RT_SIGPROCMASK => sys_rt_sigprocmask(proc, uctx.arg2(), uctx.arg3()),
+        SYS_SIGALTSTACK => sys_sigaltstack(proc, uctx.arg1()),
+        SYS_KILL => sys_kill(proc, uctx.arg0() as i32, uctx.arg1() as i32),
+        SYS_MPROTECT => sys_mprotect(proc, uctx.arg0(), uctx.arg1(), uctx.arg2()),
+        SYS_GETRANDOM => sys_getrandom(proc, uctx.arg0(), uctx.arg1()),
+        _ => {
+            warn!("Unimplemented syscall: {}", num);
+            Err(LinuxError::ENOSYS)
+        }
+    }
+}
+
+/// Poll `ready` with `process` locked until it yields a value, or until
+/// `deadline` passes (`None`), sleeping for [`POLL_INTERVAL`] with `process`
+/// unlocked in between.
+///
+/// Fails with `-EINTR` once a `kill` is pending, for the trap loop to act on.
+fn wait_until<T>(
+    process: &Mutex<ProcessState>,
+    deadline: Option<Duration>,
+    mut ready: impl FnMut(&ProcessState) -> Option<T>,
+) -> LinuxResult<Option<T>> {
+    loop {
+        let pid = {
+            let proc = process.lock();
+            if let Some(value) = ready(&proc) {
+                return Ok(Some(value));
+            }
+            proc.pid
+        };
+        if task::kill_pending(pid) {
+            return Err(LinuxError::EINTR);
+        }
+        if deadline.is_some_and(|d| axhal::time::monotonic_time() >= d) {
+            return Ok(None);
+        }
+        axtask::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Fail with `-EFAULT` unless `[uaddr, uaddr + len)` is mapped in `aspace`
+/// with the user `access` permissions.
+///
+/// The page table is consulted rather than the mapping areas, whose flags
+/// `mprotect` does not update.
+fn check_user_range(
+    aspace: &AddrSpace,
+    uaddr: usize,
+    len: usize,
+    access: MappingFlags,
+) -> LinuxResult {
+    let end = uaddr
+        .checked_add(len)
+        .and_then(|end| end.checked_next_multiple_of(PAGE_SIZE_4K))
+        .ok_or(LinuxError::EFAULT)?;
+    let start = VirtAddr::from(uaddr).align_down_4k();
+    for page in PageIter4K::new(start, VirtAddr::from(end)).ok_or(LinuxError::EFAULT)? {
+        match aspace.page_table().query(page) {
+            Ok((_, flags, _)) if flags.contains(access | MappingFlags::USER) => {}
+            _ => return Err(LinuxError::EFAULT),
+        }
+    }
+    Ok(())
+}
+
+/// Copy `data` into the user buffer at `uaddr` after checking that the whole
+/// range is mapped user-writable in `aspace`.
+fn copy_to_user(aspace: &AddrSpace, uaddr: usize, data: &[u8]) -> LinuxResult {
+    check_user_range(aspace, uaddr, data.len(), MappingFlags::WRITE)?;
+    aspace
+        .write(VirtAddr::from(uaddr), data)
+        .map_err(|_| LinuxError::EFAULT)
+}
+
+/// Copy `len` bytes from the user-readable buffer at `uaddr`.
+fn copy_from_user(aspace: &AddrSpace, uaddr: usize, len: usize) -> LinuxResult<Vec<u8>> {
+    check_user_range(aspace, uaddr, len, MappingFlags::READ)?;
+    let mut data = vec![0; len];
+    aspace
+        .read(VirtAddr::from(uaddr), &mut data)
+        .map_err(|_| LinuxError::EFAULT)?;
+    Ok(data)
+}
+
+/// Read a NUL-terminated path of at most [`PATH_MAX`] bytes from `uaddr`.
+fn read_user_path(aspace: &AddrSpace, uaddr: usize) -> LinuxResult<String> {
+    let mut bytes = Vec::new();
+    loop {
+        if bytes.len() == PATH_MAX {
+            return Err(LinuxError::ENAMETOOLONG);
+        }
+        let addr = uaddr.checked_add(bytes.len()).ok_or(LinuxError::EFAULT)?;
+        let byte = copy_from_user(aspace, addr, 1)?[0];
+        if byte == 0 {
+            break;
+        }
+        bytes.push(byte);
+    }
+    String::from_utf8(bytes).map_err(|_| LinuxError::EINVAL)
+}
+
+/// The filesystem context that paths relative to `dirfd` resolve against:
+/// the process's cwd for `AT_FDCWD`, or else the directory `dirfd` refers to.
+///
+/// Fails with `-ENODEV` when no root filesystem is mounted (initrd boot), so
+/// that case stays distinguishable from a path that does not exist.
+fn dirfd_context(proc: &ProcessState, dirfd: usize) -> LinuxResult<FsContext> {
+    let root = ROOT_FS_CONTEXT.get().ok_or(LinuxError::ENODEV)?;
+    if dirfd as isize == AT_FDCWD {
+        return Ok(root.with_current_dir(root.resolve(proc.cwd.as_str())?)?);
+    }
+    match proc.fds.get(dirfd)? {
+        FileLike::Dir(loc) => Ok(root.with_current_dir(loc.clone())?),
+        _ => Err(LinuxError::ENOTDIR),
+    }
+}
+
+/// `openat(dirfd, path, flags, mode)`: open a file or directory and return
+/// the lowest free fd referring to it.
+fn sys_openat(
+    proc: &mut ProcessState,
+    dirfd: usize,
+    path: usize,
+    flags: usize,
+    mode: usize,
+) -> LinuxResult<usize> {
+    let path = read_user_path(&proc.aspace, path)?;
+    let ctx = dirfd_context(proc, dirfd)?;
+
+    let mut options = OpenOptions::new();
+    match flags & O_ACCMODE {
+        O_WRONLY => options.write(true),
+        O_RDWR => options.read(true).write(true),
+        _ => options.read(true),
+    };
+    options
+        .append(flags & O_APPEND != 0)
+        .truncate(flags & O_TRUNC != 0)
+        .create(flags & O_CREAT != 0)
+        .create_new(flags & O_CREAT != 0 && flags & O_EXCL != 0)
+        .mode(mode as u32);
+
+    let file = match options.open(&ctx, path.as_str())? {
+        OpenResult::File(file) => FileLike::File(Arc::new(OpenFile::new(file))),
+        OpenResult::Dir(loc) => FileLike::Dir(loc),
+    };
+    debug!("[SYS_OPENAT]: {}", path);
+    Ok(proc.fds.add(file))
+}
+
+/// `read(fd, buf, count)`: read from the fd's current offset and advance it.
+///
+/// The console has no end of file, so reading it waits, with the process
+/// unlocked, until some input arrives.
+fn sys_read(
+    process: &Mutex<ProcessState>,
+    fd: usize,
+    buf: usize,
+    count: usize,
+) -> LinuxResult<usize> {
+    let mut data = vec![0; count.min(IO_CHUNK)];
+    let file = {
+        let proc = process.lock();
+        // Validate the destination first so a fault does not consume file data.
+        check_user_range(&proc.aspace, buf, data.len(), MappingFlags::WRITE)?;
+        proc.fds.get(fd)?.clone()
+    };
+    let n = match &file {
+        FileLike::Stdio if data.is_empty() => 0,
+        FileLike::Stdio => {
+            wait_until(process, None, |_| fd::console_readable().then_some(()))?;
+            fd::console_read(&mut data)
+        }
+        FileLike::File(f) => {
+            let mut offset = f.offset.lock();
+            let n = f.file.read_at(&mut data[..], *offset)?;
+            *offset += n as u64;
+            n
+        }
+        FileLike::Dir(_) => return Err(LinuxError::EISDIR),
+    };
+    copy_to_user(&process.lock().aspace, buf, &data[..n])?;
+    Ok(n)
+}
+
+/// `write(fd, buf, count)`: write at the fd's current offset (or at the end
+/// of the file for `O_APPEND`) and advance it.
+fn sys_write(proc: &ProcessState, fd: usize, buf: usize, count: usize) -> LinuxResult<usize> {
+    let file = proc.fds.get(fd)?;
+    let data = copy_from_user(&proc.aspace, buf, count.min(IO_CHUNK))?;
+    match file {
+        FileLike::Stdio => {
+            axhal::console::write_bytes(&data);
+            Ok(data.len())
+        }
+        FileLike::File(f) => {
+            let mut offset = f.offset.lock();
+            if f.file.flags().contains(FileFlags::APPEND) {
+                *offset = f.size()?;
+            }
+            let n = f.file.write_at(&data[..], *offset)?;
+            *offset += n as u64;
+            Ok(n)
+        }
+        FileLike::Dir(_) => Err(LinuxError::EISDIR),
+    }
+}
+
+/// `lseek(fd, offset, whence)`: reposition the fd's offset and return it.
+fn sys_lseek(proc: &ProcessState, fd: usize, offset: isize, whence: usize) -> LinuxResult<usize> {
+    let FileLike::File(f) = proc.fds.get(fd)? else {
+        return Err(LinuxError::ESPIPE);
+    };
+    let mut pos = f.offset.lock();
+    let base = match whence {
+        SEEK_SET => 0,
+        SEEK_CUR => *pos as i64,
+        SEEK_END => f.size()? as i64,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let new = base
+        .checked_add(offset as i64)
+        .filter(|off| *off >= 0)
+        .ok_or(LinuxError::EINVAL)?;
+    *pos = new as u64;
+    Ok(new as usize)
+}
+
+/// `struct pollfd`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+/// `ppoll(fds, nfds, tmo_p, sigmask)`: a minimal poll over the fd table.
+///
+/// The console is always writable and readable once input is pending;
+/// regular files and directories are always ready. Waits for the console
+/// by polling it until `tmo_p` expires (forever if it is NULL), with the
+/// process unlocked while it sleeps. The signal mask is ignored.
+fn sys_ppoll(
+    process: &Mutex<ProcessState>,
+    fds: usize,
+    nfds: usize,
+    tmo_p: usize,
+) -> LinuxResult<usize> {
+    if nfds > POLL_MAX_FDS {
+        return Err(LinuxError::EINVAL);
+    }
+    let proc = process.lock();
+    let deadline = match tmo_p {
+        0 => None,
+        _ => {
+            let ts = copy_from_user(&proc.aspace, tmo_p, 16)?;
+            let sec = i64::from_ne_bytes(ts[..8].try_into().unwrap());
+            let nsec = i64::from_ne_bytes(ts[8..].try_into().unwrap());
+            if sec < 0 || !(0..1_000_000_000).contains(&nsec) {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(axhal::time::monotonic_time() + Duration::new(sec as u64, nsec as u32))
+        }
+    };
+
+    let size = nfds * size_of::<PollFd>();
+    let raw = copy_from_user(&proc.aspace, fds, size)?;
+    let mut pollfds: Vec<PollFd> = raw
+        .chunks_exact(size_of::<PollFd>())
+        .map(|c| unsafe { core::ptr::read_unaligned(c.as_ptr() as *const PollFd) })
+        .collect();
+    // Fail with -EFAULT up front rather than after waiting.
+    check_user_range(&proc.aspace, fds, size, MappingFlags::WRITE)?;
+    drop(proc);
+
+    let ready = wait_until(process, deadline, |proc| {
+        let mut ready = 0;
+        for pfd in pollfds.iter_mut() {
+            pfd.revents = poll_revents(proc, pfd);
+            if pfd.revents != 0 {
+                ready += 1;
+            }
+        }
+        (ready > 0).then_some(ready)
+    })?
+    .unwrap_or(0);
+
+    let raw = unsafe { core::slice::from_raw_parts(pollfds.as_ptr() as *const u8, size) };
+    copy_to_user(&process.lock().aspace, fds, raw)?;
+    Ok(ready)
+}
+
+/// The `revents` currently due for one `pollfd`.
+fn poll_revents(proc: &ProcessState, pfd: &PollFd) -> i16 {
+    if pfd.fd < 0 {
+        return 0;
+    }
+    let ready = match proc.fds.get(pfd.fd as usize) {
+        Ok(FileLike::Stdio) if pfd.events & POLLIN != 0 && fd::console_readable() => {
+            POLLIN | POLLOUT
+        }
+        Ok(FileLike::Stdio) => POLLOUT,
+        Ok(_) => POLLIN | POLLOUT,
+        Err(_) => return POLLNVAL,
+    };
+    ready & pfd.events
+}
+
+/// `fstat(fd, statbuf)`: describe an open fd in `struct stat`.
+fn sys_fstat(proc: &ProcessState, fd: usize, statbuf: usize) -> LinuxResult<usize> {
+    let st = proc.fds.get(fd)?.stat()?;
+    copy_to_user(&proc.aspace, statbuf, st.as_bytes())?;
+    Ok(0)
+}
+
+/// `statx(dirfd, path, flags, mask, statxbuf)`.
+///
+/// With `AT_EMPTY_PATH` and an empty path this describes the open fd
+/// `dirfd`; otherwise `path` is looked up relative to `dirfd`. `mask` is
+/// ignored and every basic field is always returned.
+fn sys_statx(
+    proc: &ProcessState,
+    dirfd: usize,
+    path: usize,
+    flags: usize,
+    statxbuf: usize,
+) -> LinuxResult<usize> {
+    let path = read_user_path(&proc.aspace, path)?;
+    let st = if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        proc.fds.get(dirfd)?.stat()?
+    } else {
+        let loc = dirfd_context(proc, dirfd)?.resolve(path.as_str())?;
+        Kstat::from_metadata(&loc.metadata()?)
+    };
+    copy_to_user(&proc.aspace, statxbuf, Statx::from(&st).as_bytes())?;
+    Ok(0)
+}
+
+/// `ioctl(fd, request, argp)`: just enough terminal control for `isatty`
+/// and window-size queries on the console.
+///
+/// `TCGETS` reports the settings of a freshly opened terminal (`stty sane`)
+/// and `TIOCGWINSZ` a fixed [`CONSOLE_WINSIZE`]. Non-console fds fail with
+/// `-ENOTTY`, other requests with `-EINVAL`.
+fn sys_ioctl(proc: &ProcessState, fd: usize, request: usize, argp: usize) -> LinuxResult<usize> {
+    if !matches!(proc.fds.get(fd)?, FileLike::Stdio) {
+        return Err(LinuxError::ENOTTY);
+    }
+    match request {
+        TCGETS => {
+            // `struct termios { u32 c_iflag, c_oflag, c_cflag, c_lflag;
+            // u8 c_line; u8 c_cc[19]; }`
+            let mut termios = [0u8; 36];
+            let flags: [u32; 4] = [
+                0o2400,   // ICRNL | IXON
+                0o5,      // OPOST | ONLCR
+                0o277,    // B38400 | CS8 | CREAD
+                0o105073, // ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN
+            ];
+            for (i, flag) in flags.iter().enumerate() {
+                termios[i * 4..i * 4 + 4].copy_from_slice(&flag.to_ne_bytes());
+            }
+            // ^C ^\ DEL ^U ^D, VTIME 0, VMIN 1, VSWTC, ^Q ^S ^Z, VEOL, ^R ^O ^W ^V
+            let cc = [
+                3, 0x1c, 0x7f, 0x15, 4, 0, 1, 0, 0x11, 0x13, 0x1a, 0, 0x12, 0x0f, 0x17, 0x16,
+            ];
+            termios[17..17 + cc.len()].copy_from_slice(&cc);
+            copy_to_user(&proc.aspace, argp, &termios)?;
+            Ok(0)
+        }
+        TIOCGWINSZ => {
+            // `struct winsize { u16 ws_row, ws_col, ws_xpixel, ws_ypixel; }`
+            let (rows, cols) = CONSOLE_WINSIZE;
+            let mut winsize = [0u8; 8];
+            winsize[0..2].copy_from_slice(&rows.to_ne_bytes());
+            winsize[2..4].copy_from_slice(&cols.to_ne_bytes());
+            copy_to_user(&proc.aspace, argp, &winsize)?;
+            Ok(0)
         }
         _ => {
-            ax_println!("Unimplemented syscall: {}", num);
-            uctx.set_retval(usize::MAX); // -ENOSYS equivalent
-            None
+            warn!("[SYS_IOCTL]: unsupported request {:#x}", request);
+            Err(LinuxError::EINVAL)
+        }
+    }
+}
+
+/// `kill(pid, sig)`: terminate the process `pid` (the caller's own for 0)
+/// with `SIGKILL` or `SIGTERM`, or just check that it exists for signal 0.
+/// A target waiting in `read` or `ppoll` stops waiting to act on it.
+///
+/// There are no signal handlers, and no process groups besides the caller's,
+/// yet; other signals fail with `-EINVAL`.
+fn sys_kill(proc: &ProcessState, pid: i32, sig: i32) -> LinuxResult<usize> {
+    let pid = match pid {
+        0 => proc.pid,
+        pid if pid > 0 => pid as usize,
+        _ => return Err(LinuxError::ESRCH),
+    };
+    match sig {
+        0 if task::process_exists(pid) => Ok(0),
+        0 => Err(LinuxError::ESRCH),
+        SIGKILL | SIGTERM => task::send_kill(pid, sig).map(|_| 0),
+        1..=SIGMAX => {
+            warn!("[SYS_KILL]: signal {} is not supported", sig);
+            Err(LinuxError::EINVAL)
         }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// `rt_sigprocmask(how, set, oldset, sigsetsize)`: accepted and ignored.
+/// Nothing is ever blocked, so `oldset` reads back as empty.
+fn sys_rt_sigprocmask(proc: &ProcessState, oldset: usize, sigsetsize: usize) -> LinuxResult<usize> {
+    trace!("[SYS_RT_SIGPROCMASK]: ignored");
+    if sigsetsize != SIGSET_SIZE {
+        return Err(LinuxError::EINVAL);
+    }
+    if oldset != 0 {
+        copy_to_user(&proc.aspace, oldset, &[0; SIGSET_SIZE])?;
+    }
+    Ok(0)
+}
+
+/// `sigaltstack(ss, old_ss)`: accepted and ignored. `old_ss` always reports
+/// `SS_DISABLE`, as no alternate stack is ever installed.
+fn sys_sigaltstack(proc: &ProcessState, old_ss: usize) -> LinuxResult<usize> {
+    trace!("[SYS_SIGALTSTACK]: ignored");
+    if old_ss != 0 {
+        // `stack_t { void *ss_sp; int ss_flags; size_t ss_size; }`
+        let mut stack = [0u8; 24];
+        stack[8..12].copy_from_slice(&SS_DISABLE.to_ne_bytes());
+        copy_to_user(&proc.aspace, old_ss, &stack)?;
+    }
+    Ok(0)
+}
+
+/// `mprotect(addr, len, prot)`: change the permissions of mapped pages.
+///
+/// Writable and executable at once is refused with `-EACCES` unless the
+/// `allow-wx` feature is enabled. `PROT_NONE` pages stay mapped for the
+/// kernel only, so any user access faults.
+fn sys_mprotect(
+    proc: &mut ProcessState,
+    addr: usize,
+    len: usize,
+    prot: usize,
+) -> LinuxResult<usize> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 || !is_aligned_4k(addr) {
+        return Err(LinuxError::EINVAL);
+    }
+    let size = len
+        .checked_next_multiple_of(PAGE_SIZE_4K)
+        .ok_or(LinuxError::ENOMEM)?;
+    if size == 0 {
+        return Ok(0);
+    }
+    if prot & PROT_WRITE != 0 && prot & PROT_EXEC != 0 && !cfg!(feature = "allow-wx") {
+        warn!("[SYS_MPROTECT]: refusing W+X at {:#x}", addr);
+        return Err(LinuxError::EACCES);
+    }
+    let start = VirtAddr::from(addr);
+    if addr.checked_add(size).is_none()
+        || !proc
+            .aspace
+            .can_access_range(start, size, MappingFlags::empty())
+    {
+        return Err(LinuxError::ENOMEM);
+    }
+
+    let flags = if prot == 0 {
+        MappingFlags::READ
+    } else {
+        let mut flags = MappingFlags::USER;
+        // Write-only pages are not expressible on every architecture.
+        if prot & (PROT_READ | PROT_WRITE) != 0 {
+            flags |= MappingFlags::READ;
+        }
+        if prot & PROT_WRITE != 0 {
+            flags |= MappingFlags::WRITE;
+        }
+        if prot & PROT_EXEC != 0 {
+            flags |= MappingFlags::EXECUTE;
+        }
+        flags
+    };
+    proc.aspace
+        .protect(start, size, flags)
+        .map_err(|_| LinuxError::EINVAL)?;
+    Ok(0)
+}
+
+/// `dup3(oldfd, newfd, flags)`: make `newfd` a copy of `oldfd`.
+///
+/// `O_CLOEXEC` is accepted but has no effect, as there is no `execve` that
+/// could close the fd.
+fn sys_dup3(
+    proc: &mut ProcessState,
+    oldfd: usize,
+    newfd: usize,
+    flags: usize,
+) -> LinuxResult<usize> {
+    if flags & !O_CLOEXEC != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if oldfd == newfd {
+        return Err(LinuxError::EINVAL);
+    }
+    proc.fds.dup_to(oldfd, newfd)?;
+    Ok(newfd)
+}
+
+/// `readlinkat(dirfd, path, buf, bufsiz)`: only `/proc/self/exe` is known,
+/// resolving to the path the payload was loaded from. The result is
+/// truncated to `bufsiz` and not NUL-terminated.
+fn sys_readlinkat(
+    proc: &ProcessState,
+    path: usize,
+    buf: usize,
+    bufsiz: usize,
+) -> LinuxResult<usize> {
+    let path = read_user_path(&proc.aspace, path)?;
+    if path != "/proc/self/exe" || bufsiz == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let target = proc.exe_path.as_bytes();
+    let len = target.len().min(bufsiz);
+    copy_to_user(&proc.aspace, buf, &target[..len])?;
+    Ok(len)
+}
+
+/// `getrandom(buf, buflen, flags)`: fill `buf` with pseudo-random bytes.
+///
+/// No platform exposes an entropy source through `axhal`, so the bytes come
+/// from a SplitMix64 generator seeded with the boot-relative time. They are
+/// NOT cryptographically secure, whatever `flags` asks for; every flag is
+/// accepted and ignored. At most [`IO_CHUNK`] bytes are filled per call.
+fn sys_getrandom(proc: &ProcessState, buf: usize, buflen: usize) -> LinuxResult<usize> {
+    let len = buflen.min(IO_CHUNK);
+    check_user_range(&proc.aspace, buf, len, MappingFlags::WRITE)?;
+    let mut data = vec![0u8; len];
+    let mut state = RNG_STATE.lock();
+    if *state == 0 {
+        *state = axhal::time::monotonic_time().as_nanos() as u64 | 1;
+    }
+    for chunk in data.chunks_mut(8) {
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        chunk.copy_from_slice(&z.to_ne_bytes()[..chunk.len()]);
+    }
+    drop(state);
+    copy_to_user(&proc.aspace, buf, &data)?;
+    Ok(len)
+}
+
+/// `chdir(path)`: make the directory at `path` the cwd, recorded as its
+/// canonical absolute path.
+fn sys_chdir(proc: &mut ProcessState, path: usize) -> LinuxResult<usize> {
+    let path = read_user_path(&proc.aspace, path)?;
+    let dir = dirfd_context(proc, AT_FDCWD as usize)?.resolve(path.as_str())?;
+    dir.check_is_dir()?;
+    proc.cwd = dir.absolute_path()?.as_str().into();
+    debug!("[SYS_CHDIR]: {}", proc.cwd);
+    Ok(0)
+}
+
+/// `getcwd(buf, size)`: copy the NUL-terminated cwd into `buf`.
+///
+/// Like the raw Linux syscall, returns the number of bytes written including
+/// the terminating NUL, or `-ERANGE` if `size` cannot hold it.
+fn sys_getcwd(proc: &ProcessState, buf: usize, size: usize) -> LinuxResult<usize> {
+    let len = proc.cwd.len() + 1;
+    if size < len {
+        return Err(LinuxError::ERANGE);
     }
+    copy_to_user(&proc.aspace, buf, proc.cwd.as_bytes())?;
+    copy_to_user(&proc.aspace, buf + proc.cwd.len(), &[0])?;
+    Ok(len)
 }
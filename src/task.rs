@@ -1,57 +1,295 @@
-use axhal::uspace::{ReturnReason, UserContext};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axhal::trap::PageFaultFlags;
+use axhal::uspace::{ExceptionKind, ReturnReason, UserContext};
 use axmm::AddrSpace;
+use axsync::Mutex;
 use axtask::{AxTaskRef, TaskInner};
-use memory_addr::VirtAddr;
+use memory_addr::{MemoryAddr, PageIter4K, VirtAddr, VirtAddrRange};
+
+use crate::fd::FdTable;
+use crate::syscall::{self, SyscallOutcome};
+use crate::ustack;
+
+// `USER_ASPACE_BASE` and `USER_ASPACE_SIZE`, from `uspace-base` and
+// `uspace-size` in the axconfig file (see build.rs).
+include!(concat!(env!("OUT_DIR"), "/uspace.rs"));
+
+/// Live processes by PID, each with the terminating signal sent to it by
+/// `kill` that its trap loop has yet to act on.
+static PROCESSES: Mutex<BTreeMap<usize, Option<i32>>> = Mutex::new(BTreeMap::new());
+
+/// Ask the process `pid` to terminate with signal `sig`. It exits the next
+/// time its task returns from user space or wakes up in a waiting syscall.
+///
+/// The exit code is `128 + sig`, the shell's convention for `$?` after a
+/// signal death: tasks only have an integer exit code, and this is not an
+/// encoded `wait(2)` status.
+///
+/// Fails with `-ESRCH` if no such process is alive.
+pub fn send_kill(pid: usize, sig: i32) -> LinuxResult {
+    let mut processes = PROCESSES.lock();
+    let pending = processes.get_mut(&pid).ok_or(LinuxError::ESRCH)?;
+    pending.get_or_insert(sig);
+    Ok(())
+}
+
+/// Whether `kill` has asked the process `pid` to terminate.
+pub fn kill_pending(pid: usize) -> bool {
+    PROCESSES.lock().get(&pid).is_some_and(Option::is_some)
+}
+
+/// Whether the process `pid` is alive.
+pub fn process_exists(pid: usize) -> bool {
+    PROCESSES.lock().contains_key(&pid)
+}
+
+/// Create an empty user address space over the configured user range, with
+/// the kernel mappings copied in where the architecture needs them.
+///
+/// Every new process, the first one included, starts from one of these.
+pub fn new_user_aspace() -> AxResult<AddrSpace> {
+    axmm::new_user_aspace(VirtAddr::from(USER_ASPACE_BASE), USER_ASPACE_SIZE)
+}
+
+/// The kind of access that raised a page fault with `flags`.
+fn fault_access(flags: PageFaultFlags) -> &'static str {
+    if flags.contains(MappingFlags::EXECUTE) {
+        "instruction fetch"
+    } else if flags.contains(MappingFlags::WRITE) {
+        "write"
+    } else {
+        "read"
+    }
+}
 
-use crate::syscall;
+/// Where a faulting `vaddr` lies in `aspace`: outside the user range, on a
+/// mapped page (a permission fault), or on an unmapped page, which is placed
+/// relative to the nearer of the loaded segments and the `stack`.
+fn fault_location(aspace: &AddrSpace, vaddr: VirtAddr, stack: VirtAddrRange) -> String {
+    if !aspace.contains_range(vaddr, 1) {
+        return format!(
+            "outside user space [{:#x}, {:#x})",
+            aspace.base(),
+            aspace.end()
+        );
+    }
+    let pt = aspace.page_table();
+    if let Ok((_, flags, _)) = pt.query(vaddr.align_down_4k()) {
+        return format!("mapped {:?}", flags);
+    }
+    // The payload is linked to put its segments on consecutive pages from
+    // `APP_ENTRY` (see payload/linker.ld).
+    let code_start = VirtAddr::from(crate::APP_ENTRY);
+    let code_end = PageIter4K::new(code_start, stack.start)
+        .into_iter()
+        .flatten()
+        .find(|&page| pt.query(page).is_err())
+        .unwrap_or(stack.start);
+    let below_stack = stack.start.as_usize().saturating_sub(vaddr.as_usize());
+    if vaddr < code_start {
+        format!("unmapped, below code at {:#x}", code_start)
+    } else if vaddr >= stack.end {
+        format!(
+            "unmapped, above stack [{:#x}, {:#x})",
+            stack.start, stack.end
+        )
+    } else if vaddr - code_end < below_stack {
+        format!(
+            "unmapped, {:#x} above code [{:#x}, {:#x})",
+            vaddr - code_end,
+            code_start,
+            code_end
+        )
+    } else {
+        format!(
+            "unmapped, {:#x} below stack [{:#x}, {:#x})",
+            below_stack, stack.start, stack.end
+        )
+    }
+}
+
+/// Unregister the process `pid` and terminate the current task.
+fn exit_process(pid: usize, exit_code: i32) -> ! {
+    PROCESSES.lock().remove(&pid);
+    axtask::exit(exit_code)
+}
+
+/// Per-process state that the syscall layer operates on.
+///
+/// It is shared as an `Arc<Mutex<ProcessState>>` between whoever spawned
+/// the process and the task's trap loop, which locks it for each syscall
+/// (but not while a syscall sleeps).
+pub struct ProcessState {
+    /// The user address space.
+    pub aspace: AddrSpace,
+    /// Current working directory, always an absolute path.
+    pub cwd: String,
+    /// Path of the executable the process was loaded from.
+    pub exe_path: String,
+    /// Process ID; the ID of the single task running this process.
+    pub pid: usize,
+    /// User address recorded by `set_tid_address` (`clear_child_tid`).
+    pub clear_child_tid: usize,
+    /// Open file descriptors.
+    pub fds: FdTable,
+}
+
+impl ProcessState {
+    fn new(aspace: AddrSpace, exe_path: &str, pid: usize) -> Self {
+        Self {
+            aspace,
+            cwd: String::from("/"),
+            exe_path: String::from(exe_path),
+            pid,
+            clear_child_tid: 0,
+            fds: FdTable::with_stdio(),
+        }
+    }
+}
 
 /// Spawn a user task that enters user space and handles traps.
 ///
+/// `uspace`, loaded from `exe_path`, becomes part of the returned
+/// [`ProcessState`], which outlives the task and stays reachable from both
+/// the trap loop and the syscall layer.
+///
 /// The task:
 /// 1. Switches to the user address space page table (via scheduler)
 /// 2. Creates a UserContext and enters user mode
-/// 3. Handles syscalls and other traps
-/// 4. Exits when SYS_EXIT is received
-pub fn spawn_user_task(uspace: AddrSpace, ustack_top: VirtAddr) -> AxTaskRef {
+/// 3. Handles syscalls, yields on timer interrupts, and kills the task on
+///    fatal traps (page faults, illegal instructions, breakpoints, ...)
+/// 4. Exits when SYS_EXIT or SYS_EXIT_GROUP is received, or once `kill`
+///    has sent it SIGKILL or SIGTERM
+///
+/// User space starts at `entry`. `ustack_top` is rounded down to
+/// [`ustack::USER_STACK_ALIGN`]; an error is returned if the resulting stack
+/// pointer does not sit on top of a mapped, user writable stack. `arg` is
+/// passed to the entry point in the first argument register.
+pub fn spawn_user_task(
+    uspace: AddrSpace,
+    entry: VirtAddr,
+    ustack_top: VirtAddr,
+    exe_path: &str,
+    arg: usize,
+) -> AxResult<(AxTaskRef, Arc<Mutex<ProcessState>>)> {
     let page_table_root = uspace.page_table_root();
 
-    // Create the user context: entry point, stack top, arg0=0
-    let entry = crate::APP_ENTRY;
-    let sp = ustack_top;
+    // Create the user context: entry point, stack top, arg0=arg
+    let stack_flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+    let Some(sp) = ustack::initial_sp(ustack_top.as_usize(), |start, size| {
+        uspace.can_access_range(VirtAddr::from(start), size, stack_flags)
+    }) else {
+        error!(
+            "User stack top {:#x} is not inside a mapped user stack",
+            ustack_top
+        );
+        return Err(AxError::InvalidInput);
+    };
+    let sp = VirtAddr::from(sp);
+    if sp != ustack_top {
+        warn!(
+            "User stack top {:#x} is misaligned, rounded down to {:#x}",
+            ustack_top, sp
+        );
+    }
+
+    // Only used to place page faults relative to the stack.
+    let stack = VirtAddrRange::new(
+        (ustack_top - crate::USER_STACK_SIZE).align_down_4k(),
+        ustack_top,
+    );
 
+    // The PID is the task ID, known only once the task has been created.
+    let proc = Arc::new(Mutex::new(ProcessState::new(uspace, exe_path, 0)));
+    let task_proc = proc.clone();
     let mut task = TaskInner::new(
         move || {
-            // Keep uspace alive for the duration of this task.
-            let _uspace = uspace;
-
-            let mut uctx = UserContext::new(entry, sp, 0);
+            let pid = task_proc.lock().pid;
+            let mut uctx = UserContext::new(entry.as_usize(), sp, arg);
 
-            ax_println!(
+            info!(
                 "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
                 entry,
                 sp,
                 axtask::current().kernel_stack_top().unwrap(),
             );
 
+            // `uctx` only holds the integer registers. User FP/SIMD registers
+            // stay live in the CPU across each trap, which relies on the
+            // kernel never touching them: the x86_64 and aarch64 kernel
+            // targets are soft-float, but riscv64gc and loongarch64 are
+            // hard-float, where it holds only because the trap and syscall
+            // paths do no floating-point arithmetic (the payload's FP check
+            // catches a violation). The `fp-simd` feature saves and restores
+            // them with the task context whenever a preemption or a blocking
+            // syscall switches tasks.
             loop {
                 let reason = uctx.run();
                 match reason {
                     ReturnReason::Syscall => {
-                        if let Some(exit_code) = syscall::handle_syscall(&mut uctx) {
-                            axtask::exit(exit_code as _);
+                        match syscall::handle_syscall(&mut uctx, &task_proc) {
+                            SyscallOutcome::Continue => {}
+                            SyscallOutcome::Exit(exit_code) => exit_process(pid, exit_code),
+                            SyscallOutcome::ExitGroup(exit_code) => {
+                                // This task is the only member of its group, so
+                                // tearing down the group means exiting ourselves.
+                                exit_process(pid, exit_code)
+                            }
                         }
                     }
                     ReturnReason::PageFault(vaddr, flags) => {
-                        ax_println!("User page fault at {:#x}, flags: {:?}", vaddr, flags);
-                        axtask::exit(-1);
+                        let location = fault_location(&task_proc.lock().aspace, vaddr, stack);
+                        error!(
+                            "User page fault: {} at {:#x} ({}), flags: {:?}, pc={:#x}",
+                            fault_access(flags),
+                            vaddr,
+                            location,
+                            flags,
+                            uctx.ip()
+                        );
+                        exit_process(pid, -1);
                     }
-                    _ => {
-                        ax_println!("Unexpected trap from user space: {:?}", reason);
-                        axtask::exit(-1);
+                    ReturnReason::Interrupt => {
+                        // The interrupt has already been serviced by
+                        // `uctx.run()`. Give up the CPU on every interrupt,
+                        // timer or device, so a timer tick preempts CPU-bound
+                        // user code, then resume it.
+                        axtask::yield_now();
                     }
+                    ReturnReason::Exception(info) => {
+                        let cause = match info.kind() {
+                            ExceptionKind::Breakpoint => "breakpoint",
+                            ExceptionKind::IllegalInstruction => "illegal instruction",
+                            ExceptionKind::Misaligned => "misaligned access",
+                            ExceptionKind::Other => "exception",
+                        };
+                        error!("User {} at pc={:#x}: {:?}", cause, uctx.ip(), info);
+                        exit_process(pid, -1);
+                    }
+                    ReturnReason::Unknown => {
+                        error!("Unknown trap from user space at pc={:#x}", uctx.ip());
+                        exit_process(pid, -1);
+                    }
+                }
+
+                // Act on a `kill` before running any more user code; timer
+                // interrupts bring even a spinning task back here, and
+                // waiting syscalls return `-EINTR` early to get here.
+                let killed = PROCESSES.lock().get_mut(&pid).and_then(Option::take);
+                if let Some(sig) = killed {
+                    info!("Process {} killed by signal {}", pid, sig);
+                    exit_process(pid, 128 + sig);
                 }
             }
         },
+        // The first user process is spawned straight from `main`, with no
+        // intermediate boot task; the name only labels it in task logs.
         "userboot".into(),
         crate::KERNEL_STACK_SIZE,
     );
@@ -59,6 +297,9 @@ pub fn spawn_user_task(uspace: AddrSpace, ustack_top: VirtAddr) -> AxTaskRef {
     // Set the page table root so the scheduler switches to user space
     // page table when this task is scheduled.
     task.ctx_mut().set_page_table_root(page_table_root);
+    let pid = task.id().as_u64() as usize;
+    proc.lock().pid = pid;
+    PROCESSES.lock().insert(pid, None);
 
-    axtask::spawn_task(task)
+    Ok((axtask::spawn_task(task), proc))
 }
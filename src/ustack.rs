@@ -0,0 +1,43 @@
+//! Choosing the stack pointer a user task starts with.
+
+/// Stack pointer alignment required at user entry by every supported ABI.
+pub const USER_STACK_ALIGN: usize = 16;
+
+/// The initial stack pointer for a stack whose top is `ustack_top`: the top
+/// rounded down to [`USER_STACK_ALIGN`], or `None` unless the aligned slot
+/// just below it passes `is_stack(start, size)`.
+pub fn initial_sp(ustack_top: usize, is_stack: impl FnOnce(usize, usize) -> bool) -> Option<usize> {
+    let sp = ustack_top & !(USER_STACK_ALIGN - 1);
+    if sp < USER_STACK_ALIGN || !is_stack(sp - USER_STACK_ALIGN, USER_STACK_ALIGN) {
+        return None;
+    }
+    Some(sp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stack mapped over `[0x1_0000, 0x2_0000)`.
+    fn in_stack(start: usize, size: usize) -> bool {
+        start >= 0x1_0000 && start + size <= 0x2_0000
+    }
+
+    #[test]
+    fn aligned_top_is_kept() {
+        assert_eq!(initial_sp(0x2_0000, in_stack), Some(0x2_0000));
+    }
+
+    #[test]
+    fn misaligned_top_is_rounded_down() {
+        assert_eq!(initial_sp(0x2_0000 - 8, in_stack), Some(0x2_0000 - 16));
+        assert_eq!(initial_sp(0x1_fff1, in_stack), Some(0x1_fff0));
+    }
+
+    #[test]
+    fn top_outside_the_stack_is_rejected() {
+        assert_eq!(initial_sp(0x2_0010, in_stack), None);
+        assert_eq!(initial_sp(0x1_0008, in_stack), None);
+        assert_eq!(initial_sp(8, |_, _| true), None);
+    }
+}
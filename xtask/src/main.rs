@@ -1,7 +1,18 @@
-use clap::{Parser, Subcommand};
-use std::io::Write;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{self, Command};
+use std::process::{self, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exit code of `cargo xtask run` when `--timeout` expires (as timeout(1)).
+const TIMEOUT_EXIT_CODE: i32 = 124;
+/// Lines of guest output replayed when `--timeout` expires.
+const TIMEOUT_TAIL_LINES: usize = 30;
+/// Start of the line the kernel logs with the user task's exit code.
+const GUEST_EXIT_MARKER: &str = "monolithic kernel exit [";
 
 /// ArceOS userprivilege multi-architecture build & run tool
 #[derive(Parser)]
@@ -20,19 +31,116 @@ enum Cmd {
     Build {
         #[arg(long, default_value = "riscv64")]
         arch: String,
+        #[arg(long, value_enum, default_value_t = Profile::Release)]
+        profile: Profile,
+        #[arg(long, value_enum, default_value_t = BootMode::Blk)]
+        boot_mode: BootMode,
+        /// Extra kernel cargo features, comma-separated (e.g. verify-load)
+        #[arg(long, value_delimiter = ',', value_name = "FEATURES")]
+        features: Vec<String>,
     },
     /// Build and run the kernel in QEMU
     Run {
         #[arg(long, default_value = "riscv64")]
         arch: String,
+        #[arg(long, value_enum, default_value_t = Profile::Release)]
+        profile: Profile,
+        #[arg(long, value_enum, default_value_t = BootMode::Blk)]
+        boot_mode: BootMode,
+        /// Extra kernel cargo features, comma-separated (e.g. verify-load)
+        #[arg(long, value_delimiter = ',', value_name = "FEATURES")]
+        features: Vec<String>,
+        /// Use an already built payload ELF instead of building it
+        #[arg(long)]
+        payload_bin: Option<PathBuf>,
+        /// Kill QEMU after this many seconds and exit with code 124
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+        /// Write a JSON summary of the run to this file (`-` for stdout)
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+        /// QEMU CPU model, overriding the per-arch default (cortex-a72 on
+        /// aarch64, the machine's default elsewhere)
+        #[arg(long, value_name = "MODEL")]
+        cpu: Option<String>,
+        /// Kernel command line, e.g. "log=debug init=/sbin/origin" (riscv64
+        /// and aarch64 only, which hand it over in the device tree)
+        #[arg(long, visible_alias = "kernel-cmdline", value_name = "STRING")]
+        cmdline: Option<String>,
+        /// Print every command and the disk image contents instead of
+        /// building, writing or running anything
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
-#[allow(dead_code)]
+/// Cargo build profile for both the payload and the kernel.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Profile {
+    /// Unoptimized build with full debug info (for GDB)
+    Debug,
+    /// Optimized build
+    Release,
+}
+
+impl Profile {
+    /// Extra `cargo build` flags that select this profile.
+    fn cargo_args(self) -> &'static [&'static str] {
+        match self {
+            Profile::Debug => &[],
+            Profile::Release => &["--release"],
+        }
+    }
+
+    /// Directory name cargo emits artifacts into under `target/<triple>/`.
+    fn dir_name(self) -> &'static str {
+        match self {
+            Profile::Debug => "debug",
+            Profile::Release => "release",
+        }
+    }
+}
+
+/// How the payload reaches the kernel.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BootMode {
+    /// `/sbin/origin` on a FAT32 image attached as a virtio-blk disk
+    Blk,
+    /// The payload itself passed as QEMU's `-initrd` (needs a device tree)
+    Initrd,
+}
+
+impl BootMode {
+    /// Kernel cargo feature that selects this boot mode.
+    fn kernel_feature(self) -> &'static str {
+        match self {
+            BootMode::Blk => "blk",
+            BootMode::Initrd => "initrd",
+        }
+    }
+}
+
+/// Which kernel image QEMU is given via `-kernel`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BootArtifact {
+    /// Boot the kernel ELF directly.
+    Elf,
+    /// Flatten the ELF to a raw binary with `rust-objcopy` and boot that.
+    Bin,
+}
+
 struct ArchInfo {
     target: &'static str,
     platform: &'static str,
     objcopy_arch: &'static str,
+    boot_artifact: BootArtifact,
+    /// Ubuntu package providing `qemu-system-<arch>`.
+    qemu_package: &'static str,
+    /// Whether the kernel is handed a device tree that locates an initrd.
+    has_dtb: bool,
+    /// CPU model passed to QEMU unless `--cpu` overrides it; `None` keeps
+    /// the machine's default.
+    qemu_cpu: Option<&'static str>,
 }
 
 fn arch_info(arch: &str) -> ArchInfo {
@@ -41,21 +149,37 @@ fn arch_info(arch: &str) -> ArchInfo {
             target: "riscv64gc-unknown-none-elf",
             platform: "riscv64-qemu-virt",
             objcopy_arch: "riscv64",
+            boot_artifact: BootArtifact::Bin,
+            qemu_package: "qemu-system-riscv64",
+            has_dtb: true,
+            qemu_cpu: None,
         },
         "aarch64" => ArchInfo {
             target: "aarch64-unknown-none-softfloat",
             platform: "aarch64-qemu-virt",
             objcopy_arch: "aarch64",
+            boot_artifact: BootArtifact::Bin,
+            qemu_package: "qemu-system-arm",
+            has_dtb: true,
+            qemu_cpu: Some("cortex-a72"),
         },
         "x86_64" => ArchInfo {
             target: "x86_64-unknown-none",
             platform: "x86-pc",
             objcopy_arch: "x86_64",
+            boot_artifact: BootArtifact::Elf,
+            qemu_package: "qemu-system-x86",
+            has_dtb: false,
+            qemu_cpu: None,
         },
         "loongarch64" => ArchInfo {
             target: "loongarch64-unknown-none",
             platform: "loongarch64-qemu-virt",
             objcopy_arch: "loongarch64",
+            boot_artifact: BootArtifact::Bin,
+            qemu_package: "qemu-system-misc",
+            has_dtb: false,
+            qemu_cpu: None,
         },
         _ => {
             eprintln!(
@@ -72,13 +196,59 @@ fn project_root() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
 }
 
-fn install_config(root: &Path, arch: &str) {
+/// Directory under the cargo `target_dir` holding the build artifacts for the
+/// given target and profile.
+fn artifact_dir(target_dir: &Path, info: &ArchInfo, profile: Profile) -> PathBuf {
+    target_dir.join(info.target).join(profile.dir_name())
+}
+
+/// Cargo target directory of the payload, kept apart from the kernel's so
+/// both can build at once without waiting on each other's build lock.
+fn payload_target_dir(root: &Path) -> PathBuf {
+    root.join("target").join("payload")
+}
+
+/// Per-arch directory for generated run artifacts (disk image, kernel bin),
+/// so switching arches never picks up another arch's files.
+fn output_dir(root: &Path, arch: &str) -> PathBuf {
+    root.join("target").join(arch)
+}
+
+/// Returns `true` if an executable named `tool` exists in a `PATH` directory.
+fn tool_on_path(tool: &str) -> bool {
+    let exe = format!("{tool}{}", std::env::consts::EXE_SUFFIX);
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(&exe).is_file()))
+}
+
+/// Exit with an actionable message if the host tool `tool` is missing.
+fn require_tool(tool: &str, install_hint: &str) {
+    if !tool_on_path(tool) {
+        eprintln!("Error: `{tool}` not found in PATH.");
+        eprintln!("Install it with: {install_hint}");
+        process::exit(1);
+    }
+}
+
+fn require_objcopy() {
+    require_tool(
+        "rust-objcopy",
+        "cargo install cargo-binutils && rustup component add llvm-tools",
+    );
+}
+
+fn install_config(root: &Path, arch: &str, dry_run: bool) {
     let src = root.join("configs").join(format!("{arch}.toml"));
     let dst = root.join(".axconfig.toml");
     if !src.exists() {
         eprintln!("Error: config file not found: {}", src.display());
         process::exit(1);
     }
+    check_config(&src, arch);
+    if dry_run {
+        println!("Would install config: {} -> .axconfig.toml", src.display());
+        return;
+    }
     std::fs::copy(&src, &dst).unwrap_or_else(|e| {
         eprintln!("Error: failed to copy config: {}", e);
         process::exit(1);
@@ -86,63 +256,227 @@ fn install_config(root: &Path, arch: &str) {
     println!("Installed config: {} -> .axconfig.toml", src.display());
 }
 
-/// Build the user-space payload binary for the target architecture.
-/// Equivalent to `make payload` in the original workflow.
-fn build_payload(root: &Path, info: &ArchInfo) -> PathBuf {
-    println!("Building payload for {} ...", info.target);
-    let status = Command::new("cargo")
-        .args([
-            "build",
-            "--release",
-            "--target",
-            info.target,
-            "--bin",
-            "origin",
-            "--features",
-            "payload",
-            "--manifest-path",
-            root.join("Cargo.toml").to_str().unwrap(),
-        ])
-        .status()
-        .expect("failed to execute cargo build for payload");
-    if !status.success() {
-        eprintln!("Error: payload build failed");
-        process::exit(status.code().unwrap_or(1));
+/// Exit with a clear message unless `path` parses as TOML and declares the
+/// `arch` and `platform` that `arch_info(arch)` expects, so a config copied
+/// from another arch fails here instead of deep inside the kernel build.
+fn check_config(path: &Path, arch: &str) {
+    let doc = read_toml(path);
+    let expected = [("arch", arch), ("platform", arch_info(arch).platform)];
+    for (key, want) in expected {
+        match doc.get(key).and_then(|item| item.as_str()) {
+            Some(found) if found == want => {}
+            Some(found) => {
+                eprintln!(
+                    "Error: {} declares {key} = \"{found}\", but --arch {arch} needs {key} = \"{want}\"",
+                    path.display()
+                );
+                process::exit(1);
+            }
+            None => {
+                eprintln!(
+                    "Error: {} has no top-level `{key}` string (expected \"{want}\")",
+                    path.display()
+                );
+                process::exit(1);
+            }
+        }
     }
+}
 
-    // Objcopy to flat binary
-    let elf = root
-        .join("target")
-        .join(info.target)
-        .join("release")
-        .join("origin");
-    let bin = elf.with_extension("bin");
+/// Read and parse the TOML file at `path`.
+fn read_toml(path: &Path) -> toml_edit::DocumentMut {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Error: failed to read {}: {}", path.display(), e);
+        process::exit(1);
+    });
+    text.parse().unwrap_or_else(|e| {
+        eprintln!("Error: {} is not valid TOML:\n{}", path.display(), e);
+        process::exit(1);
+    })
+}
 
-    let status = Command::new("rust-objcopy")
-        .args([
-            &format!("--binary-architecture={}", info.objcopy_arch),
-            elf.to_str().unwrap(),
-            "--strip-all",
-            "-O",
-            "binary",
-            bin.to_str().unwrap(),
-        ])
-        .status()
-        .expect("failed to execute rust-objcopy for payload");
-    if !status.success() {
-        eprintln!("Error: payload objcopy failed");
-        process::exit(status.code().unwrap_or(1));
+/// Source file of the kernel binary, relative to the project root.
+const KERNEL_MAIN: &str = "src/main.rs";
+/// Source file of the payload binary, relative to the project root.
+const PAYLOAD_MAIN: &str = "payload/src/main.rs";
+
+/// Names of the kernel and payload binaries, i.e. of their build artifacts.
+struct BinNames {
+    kernel: String,
+    payload: String,
+}
+
+/// Read the binary names from the root `Cargo.toml`: the `[[bin]]` built
+/// from [`KERNEL_MAIN`] (or else the package name, cargo's default) and the
+/// `[[bin]]` built from [`PAYLOAD_MAIN`].
+fn bin_names(root: &Path) -> BinNames {
+    let manifest = root.join("Cargo.toml");
+    let doc = read_toml(&manifest);
+    let bin_built_from = |main: &str| {
+        doc.get("bin")?
+            .as_array_of_tables()?
+            .iter()
+            .find(|bin| bin.get("path").and_then(|path| path.as_str()) == Some(main))?
+            .get("name")?
+            .as_str()
+            .map(String::from)
+    };
+    let kernel = bin_built_from(KERNEL_MAIN)
+        .or_else(|| doc.get("package")?.get("name")?.as_str().map(String::from))
+        .unwrap_or_else(|| {
+            eprintln!(
+                "Error: {} names no kernel binary (no [package] name)",
+                manifest.display()
+            );
+            process::exit(1);
+        });
+    let payload = bin_built_from(PAYLOAD_MAIN).unwrap_or_else(|| {
+        eprintln!(
+            "Error: {} has no [[bin]] with path = \"{PAYLOAD_MAIN}\"",
+            manifest.display()
+        );
+        process::exit(1);
+    });
+    BinNames { kernel, payload }
+}
+
+/// Exit with an error unless the `what` build produced `artifact`.
+fn require_artifact(artifact: &Path, what: &str) {
+    if !artifact.is_file() {
+        eprintln!(
+            "Error: the {what} build succeeded but {} does not exist; \
+             does the binary name in Cargo.toml match?",
+            artifact.display()
+        );
+        process::exit(1);
     }
+}
 
-    println!("Payload built: {}", bin.display());
-    bin
+/// `cmd` as a shell-style line, with the environment variables it sets.
+fn command_line(cmd: &Command) -> String {
+    let envs = cmd.get_envs().filter_map(|(key, value)| {
+        Some(format!(
+            "{}={}",
+            key.to_string_lossy(),
+            value?.to_string_lossy()
+        ))
+    });
+    let program = std::iter::once(cmd.get_program().to_string_lossy().into_owned());
+    let args = cmd.get_args().map(|arg| arg.to_string_lossy().into_owned());
+    envs.chain(program)
+        .chain(args)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Run `cmd` to completion, or with `dry_run` only print it and report
+/// success.
+fn run_status(mut cmd: Command, dry_run: bool, what: &str) -> ExitStatus {
+    if dry_run {
+        println!("Would run: {}", command_line(&cmd));
+        return ExitStatus::default();
+    }
+    cmd.status()
+        .unwrap_or_else(|e| panic!("failed to execute {what}: {e}"))
+}
+
+/// The `rust-objcopy` command flattening `elf` into the raw binary `bin`.
+fn objcopy_command(elf: &Path, bin: &Path, objcopy_arch: &str) -> Command {
+    let mut cmd = Command::new("rust-objcopy");
+    cmd.args([
+        &format!("--binary-architecture={objcopy_arch}"),
+        elf.to_str().unwrap(),
+        "--strip-all",
+        "-O",
+        "binary",
+        bin.to_str().unwrap(),
+    ]);
+    cmd
+}
+
+/// The `cargo build` command for the user-space payload.
+fn payload_cargo(root: &Path, info: &ArchInfo, profile: Profile, names: &BinNames) -> Command {
+    let mut cmd = Command::new("cargo");
+    cmd.arg("build").args(profile.cargo_args()).args([
+        "--target",
+        info.target,
+        "--bin",
+        &names.payload,
+        "--features",
+        "payload",
+        "--manifest-path",
+        root.join("Cargo.toml").to_str().unwrap(),
+        "--target-dir",
+        payload_target_dir(root).to_str().unwrap(),
+    ]);
+    cmd
+}
+
+/// Path of the built payload ELF, which the kernel loads as it is; it must
+/// exist unless `dry_run`.
+fn payload_elf(
+    root: &Path,
+    info: &ArchInfo,
+    profile: Profile,
+    names: &BinNames,
+    dry_run: bool,
+) -> PathBuf {
+    let elf = artifact_dir(&payload_target_dir(root), info, profile).join(&names.payload);
+    if !dry_run {
+        require_artifact(&elf, "payload");
+        println!("Payload built: {}", elf.display());
+    }
+    elf
+}
+
+/// Validate a prebuilt payload ELF given via `--payload-bin`.
+fn prebuilt_payload(path: &Path) -> PathBuf {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.is_file() && meta.len() > 0 => {
+            let mut magic = [0u8; 4];
+            let is_elf = std::fs::File::open(path)
+                .and_then(|mut f| f.read_exact(&mut magic))
+                .is_ok_and(|_| &magic == b"\x7fELF");
+            if !is_elf {
+                eprintln!(
+                    "Error: prebuilt payload {} is not an ELF file; the kernel loads \
+                     the payload's ELF segments, not a flat binary",
+                    path.display()
+                );
+                process::exit(1);
+            }
+        }
+        Ok(_) => {
+            eprintln!(
+                "Error: prebuilt payload {} is empty or not a file",
+                path.display()
+            );
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: prebuilt payload {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+    println!("Using prebuilt payload: {}", path.display());
+    path.to_path_buf()
 }
 
 /// Create a 64MB FAT32 disk image containing `/sbin/origin`.
 /// Equivalent to `./update_disk.sh ./payload/origin/origin`.
-fn create_fat_disk_image(path: &Path, payload_bin: &Path) {
+fn create_fat_disk_image(path: &Path, payload_bin: &Path, dry_run: bool) {
     const DISK_SIZE: u64 = 64 * 1024 * 1024;
 
+    if dry_run {
+        println!(
+            "Would create FAT32 disk image: {} ({}MB) with /sbin/origin = {}",
+            path.display(),
+            DISK_SIZE / (1024 * 1024),
+            payload_bin.display()
+        );
+        return;
+    }
+
     // Read the payload binary
     let payload_data = std::fs::read(payload_bin).unwrap_or_else(|e| {
         eprintln!(
@@ -154,6 +488,13 @@ fn create_fat_disk_image(path: &Path, payload_bin: &Path) {
     });
     println!("Payload binary size: {} bytes", payload_data.len());
 
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).unwrap_or_else(|e| {
+            eprintln!("Error: failed to create {}: {}", dir.display(), e);
+            process::exit(1);
+        });
+    }
+
     // Create or truncate the image file
     let file = std::fs::OpenOptions::new()
         .read(true)
@@ -188,14 +529,28 @@ fn create_fat_disk_image(path: &Path, payload_bin: &Path) {
             process::exit(1);
         });
 
-        // Write payload as /sbin/origin
+        // Write payload as /sbin/origin; `write_all` retries short writes
         let mut f = root_dir.create_file("sbin/origin").unwrap_or_else(|e| {
             eprintln!("Error: failed to create /sbin/origin: {}", e);
             process::exit(1);
         });
-        f.write_all(&payload_data).unwrap();
-        f.flush().unwrap();
+        f.write_all(&payload_data)
+            .and_then(|_| f.flush())
+            .unwrap_or_else(|e| {
+                eprintln!("Error: failed to write /sbin/origin: {}", e);
+                process::exit(1);
+            });
+        drop(f);
+        drop(root_dir);
+
+        // Unmount so the FAT and directory entries are committed to the image
+        fs.unmount().unwrap_or_else(|e| {
+            eprintln!("Error: failed to unmount FAT filesystem: {}", e);
+            process::exit(1);
+        });
     }
+    drop(file);
+    verify_fat_disk_image(path, &payload_data);
 
     println!(
         "Created FAT32 disk image: {} ({}MB) with /sbin/origin",
@@ -204,56 +559,247 @@ fn create_fat_disk_image(path: &Path, payload_bin: &Path) {
     );
 }
 
-/// Build the kernel.
-fn do_build(root: &Path, info: &ArchInfo) {
+/// The `cargo build` command for the kernel, with the boot mode's feature
+/// and any extra `features`.
+fn kernel_cargo(
+    root: &Path,
+    info: &ArchInfo,
+    profile: Profile,
+    boot_mode: BootMode,
+    features: &[String],
+) -> Command {
     let manifest = root.join("Cargo.toml");
     let ax_config = root.join(".axconfig.toml");
-    let status = Command::new("cargo")
+    let features = std::iter::once(boot_mode.kernel_feature())
+        .chain(features.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut cmd = Command::new("cargo");
+    cmd.arg("build")
+        .args(profile.cargo_args())
         .args([
-            "build",
-            "--release",
             "--target",
             info.target,
             "--features",
-            "axstd",
+            &features,
             "--manifest-path",
             manifest.to_str().unwrap(),
         ])
         // Ensure dependencies read the intended config regardless of subprocess cwd.
-        .env("AX_CONFIG_PATH", ax_config.to_str().unwrap())
-        .status()
-        .expect("failed to execute cargo build");
+        .env("AX_CONFIG_PATH", ax_config.to_str().unwrap());
+    cmd
+}
+
+/// Re-open the FAT image at `path` and check that `/sbin/origin` reads back
+/// as exactly `payload_data`.
+fn verify_fat_disk_image(path: &Path, payload_data: &[u8]) {
+    let fail = |what: &str| -> ! {
+        eprintln!(
+            "Error: verifying /sbin/origin in {} failed: {}",
+            path.display(),
+            what
+        );
+        process::exit(1);
+    };
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    let fs = fatfs::FileSystem::new(&file, fatfs::FsOptions::new())
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    let mut data = Vec::new();
+    fs.root_dir()
+        .open_file("sbin/origin")
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    if data.len() != payload_data.len() {
+        fail(&format!(
+            "read back {} bytes, expected {}",
+            data.len(),
+            payload_data.len()
+        ));
+    }
+    if data != payload_data {
+        fail("contents differ from the payload");
+    }
+}
+
+/// Build the kernel.
+fn do_build(
+    root: &Path,
+    info: &ArchInfo,
+    profile: Profile,
+    boot_mode: BootMode,
+    features: &[String],
+    dry_run: bool,
+) {
+    let cmd = kernel_cargo(root, info, profile, boot_mode, features);
+    let status = run_status(cmd, dry_run, "cargo build");
     if !status.success() {
-        eprintln!("Error: cargo build failed");
+        eprintln!("Error: kernel build failed");
         process::exit(status.code().unwrap_or(1));
     }
 }
 
+/// Build the payload (equivalent to `make payload` in the original
+/// workflow) and the kernel concurrently, returning the payload ELF.
+/// Both builds run to completion before any failure is reported, so a
+/// failing payload build and a failing kernel build are each named.
+fn build_payload_and_kernel(
+    root: &Path,
+    info: &ArchInfo,
+    profile: Profile,
+    boot_mode: BootMode,
+    features: &[String],
+    names: &BinNames,
+    dry_run: bool,
+) -> PathBuf {
+    if dry_run {
+        let payload = payload_cargo(root, info, profile, names);
+        println!("Would run: {}", command_line(&payload));
+        let kernel = kernel_cargo(root, info, profile, boot_mode, features);
+        println!("Would run: {}", command_line(&kernel));
+        return payload_elf(root, info, profile, names, true);
+    }
+    println!("Building payload and kernel for {} ...", info.target);
+    let mut payload = payload_cargo(root, info, profile, names)
+        .spawn()
+        .expect("failed to execute cargo build for payload");
+    let mut kernel = kernel_cargo(root, info, profile, boot_mode, features)
+        .spawn()
+        .expect("failed to execute cargo build");
+    let payload_status = payload.wait().expect("failed to wait for payload build");
+    let kernel_status = kernel.wait().expect("failed to wait for kernel build");
+
+    if !payload_status.success() {
+        eprintln!("Error: payload build failed");
+    }
+    if !kernel_status.success() {
+        eprintln!("Error: kernel build failed");
+    }
+    if let Some(failed) = [payload_status, kernel_status]
+        .iter()
+        .find(|status| !status.success())
+    {
+        process::exit(failed.code().unwrap_or(1));
+    }
+    payload_elf(root, info, profile, names, false)
+}
+
+/// Path of the built kernel ELF, which must exist unless `dry_run`.
+fn kernel_elf(
+    root: &Path,
+    info: &ArchInfo,
+    profile: Profile,
+    names: &BinNames,
+    dry_run: bool,
+) -> PathBuf {
+    let elf = artifact_dir(&root.join("target"), info, profile).join(&names.kernel);
+    if !dry_run {
+        require_artifact(&elf, "kernel");
+    }
+    elf
+}
+
 /// Convert ELF to raw binary.
-fn do_objcopy(elf: &Path, bin: &Path, objcopy_arch: &str) {
-    let status = Command::new("rust-objcopy")
-        .args([
-            &format!("--binary-architecture={objcopy_arch}"),
-            elf.to_str().unwrap(),
-            "--strip-all",
-            "-O",
-            "binary",
-            bin.to_str().unwrap(),
-        ])
-        .status()
-        .expect("failed to execute rust-objcopy");
+fn do_objcopy(elf: &Path, bin: &Path, objcopy_arch: &str, dry_run: bool) {
+    if !dry_run {
+        require_objcopy();
+    }
+    let status = run_status(
+        objcopy_command(elf, bin, objcopy_arch),
+        dry_run,
+        "rust-objcopy",
+    );
     if !status.success() {
         eprintln!("Error: rust-objcopy failed");
         process::exit(status.code().unwrap_or(1));
     }
 }
 
-/// Run QEMU with VirtIO block device.
-fn do_run_qemu(arch: &str, elf: &Path, bin: &Path, disk: &Path) {
-    let mem = "128M";
-    let smp = "1";
+/// Optional knobs of a QEMU run.
+struct QemuOptions<'a> {
+    /// Kill QEMU after this many seconds.
+    timeout: Option<u64>,
+    /// CPU model replacing the arch's default.
+    cpu: Option<&'a str>,
+    /// Kernel command line, passed with `-append`.
+    cmdline: Option<&'a str>,
+    /// Only print the QEMU command line.
+    dry_run: bool,
+}
+
+/// A finished QEMU run.
+struct QemuRun {
+    /// The command line QEMU was started with.
+    command: String,
+    /// QEMU's exit code, [`TIMEOUT_EXIT_CODE`] if it was killed, or 0 for a
+    /// dry run.
+    qemu_exit_code: i32,
+    /// The payload's exit code, as logged by the kernel, if it got that far.
+    guest_exit_code: Option<i32>,
+}
+
+/// Run QEMU booting the given kernel image, with `image` attached as a
+/// VirtIO block device or passed as the initrd depending on `boot_mode`.
+fn do_run_qemu(
+    arch: &str,
+    info: &ArchInfo,
+    kernel: &Path,
+    boot_mode: BootMode,
+    image: &Path,
+    opts: &QemuOptions,
+) -> QemuRun {
     let qemu = format!("qemu-system-{arch}");
+    if !opts.dry_run {
+        require_tool(
+            &qemu,
+            &format!(
+                "sudo apt install {} (Ubuntu) or brew install qemu (macOS)",
+                info.qemu_package
+            ),
+        );
+    }
 
+    let args = qemu_args(arch, info, kernel, boot_mode, image, opts);
+    let command = format!("{} {}", qemu, args.join(" "));
+    if opts.dry_run {
+        println!("Would run: {command}");
+        return QemuRun {
+            command,
+            qemu_exit_code: 0,
+            guest_exit_code: None,
+        };
+    }
+    println!("Running: {command}");
+    let mut cmd = Command::new(&qemu);
+    cmd.args(&args);
+    let timeout = opts.timeout.map(Duration::from_secs);
+    let (status, guest_exit_code) = run_with_timeout(cmd, &qemu, timeout);
+    let qemu_exit_code = match status {
+        Some(status) => status.code().unwrap_or(1),
+        None => TIMEOUT_EXIT_CODE,
+    };
+    QemuRun {
+        command,
+        qemu_exit_code,
+        guest_exit_code,
+    }
+}
+
+/// QEMU's arguments for booting `kernel` with `image` attached per
+/// `boot_mode`.
+fn qemu_args(
+    arch: &str,
+    info: &ArchInfo,
+    kernel: &Path,
+    boot_mode: BootMode,
+    image: &Path,
+    opts: &QemuOptions,
+) -> Vec<String> {
+    let mem = "128M";
+    let smp = "1";
     let mut args: Vec<String> = vec![
         "-m".into(),
         mem.into(),
@@ -269,98 +815,477 @@ fn do_run_qemu(arch: &str, elf: &Path, bin: &Path, disk: &Path) {
                 "virt".into(),
                 "-bios".into(),
                 "default".into(),
-                "-kernel".into(),
-                bin.to_str().unwrap().into(),
             ]);
         }
         "aarch64" => {
-            args.extend([
-                "-cpu".into(),
-                "cortex-a72".into(),
-                "-machine".into(),
-                "virt".into(),
-                "-kernel".into(),
-                bin.to_str().unwrap().into(),
-            ]);
+            args.extend(["-machine".into(), "virt".into()]);
         }
         "x86_64" => {
-            args.extend([
-                "-machine".into(),
-                "q35".into(),
-                "-kernel".into(),
-                elf.to_str().unwrap().into(),
-            ]);
+            args.extend(["-machine".into(), "q35".into()]);
         }
         "loongarch64" => {
-            args.extend([
-                "-machine".into(),
-                "virt".into(),
-                "-kernel".into(),
-                bin.to_str().unwrap().into(),
-            ]);
+            args.extend(["-machine".into(), "virt".into()]);
         }
         _ => unreachable!(),
     }
+    if let Some(cpu) = opts.cpu.or(info.qemu_cpu) {
+        args.extend(["-cpu".into(), cpu.into()]);
+    }
+    args.extend(["-kernel".into(), kernel.to_str().unwrap().into()]);
+    // QEMU puts `-append` in the device tree's `/chosen/bootargs`, the only
+    // place the kernel looks for it.
+    match opts.cmdline {
+        Some(cmdline) if info.has_dtb => args.extend(["-append".into(), cmdline.into()]),
+        Some(_) => {
+            eprintln!("Warning: --cmdline is ignored on {arch}, which boots without a device tree")
+        }
+        None => {}
+    }
 
-    // VirtIO block device
-    args.extend([
-        "-drive".into(),
-        format!("file={},format=raw,if=none,id=disk0", disk.display()),
-        "-device".into(),
-        "virtio-blk-pci,drive=disk0".into(),
-    ]);
+    match boot_mode {
+        BootMode::Blk => args.extend([
+            "-drive".into(),
+            format!("file={},format=raw,if=none,id=disk0", image.display()),
+            "-device".into(),
+            "virtio-blk-pci,drive=disk0".into(),
+        ]),
+        BootMode::Initrd => args.extend(["-initrd".into(), image.display().to_string()]),
+    }
+    args
+}
 
-    println!("Running: {} {}", qemu, args.join(" "));
-    let status = Command::new(&qemu)
-        .args(&args)
-        .status()
-        .unwrap_or_else(|e| {
-            eprintln!("Error: failed to run {}: {}", qemu, e);
+/// What has been seen on QEMU's stdout so far.
+#[derive(Default)]
+struct GuestOutput {
+    /// The last [`TIMEOUT_TAIL_LINES`] lines.
+    tail: VecDeque<String>,
+    /// The exit code from the kernel's [`GUEST_EXIT_MARKER`] line.
+    exit_code: Option<i32>,
+}
+
+/// Parse the exit code out of a `monolithic kernel exit [N] normally!` line.
+fn parse_guest_exit(line: &str) -> Option<i32> {
+    let (_, rest) = line.split_once(GUEST_EXIT_MARKER)?;
+    rest.split_once(']')?.0.parse().ok()
+}
+
+/// Run `cmd`, echoing its stdout and watching it for the guest's exit code,
+/// and kill it once `timeout` (if any) has elapsed.
+///
+/// On expiry the last [`TIMEOUT_TAIL_LINES`] lines of output are repeated on
+/// stderr and the returned status is `None`.
+fn run_with_timeout(
+    mut cmd: Command,
+    name: &str,
+    timeout: Option<Duration>,
+) -> (Option<ExitStatus>, Option<i32>) {
+    let mut child = cmd.stdout(Stdio::piped()).spawn().unwrap_or_else(|e| {
+        eprintln!("Error: failed to run {}: {}", name, e);
+        process::exit(1);
+    });
+
+    let output = Arc::new(Mutex::new(GuestOutput::default()));
+    let reader = {
+        let stdout = child.stdout.take().unwrap();
+        let output = Arc::clone(&output);
+        thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            let mut line = Vec::new();
+            while stdout.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+                let mut out = std::io::stdout().lock();
+                let _ = out.write_all(&line).and_then(|_| out.flush());
+                let text = String::from_utf8_lossy(&line).trim_end().to_string();
+                let mut output = output.lock().unwrap();
+                if let Some(code) = parse_guest_exit(&text) {
+                    output.exit_code = Some(code);
+                }
+                if output.tail.len() == TIMEOUT_TAIL_LINES {
+                    output.tail.pop_front();
+                }
+                output.tail.push_back(text);
+                line.clear();
+            }
+        })
+    };
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                let _ = reader.join();
+                return (Some(status), output.lock().unwrap().exit_code);
+            }
+            Ok(None) if deadline.is_none_or(|deadline| Instant::now() < deadline) => {
+                thread::sleep(Duration::from_millis(100))
+            }
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Error: failed to wait for {}: {}", name, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Don't join the reader: a grandchild may still hold the pipe open.
+    let _ = child.kill();
+    let _ = child.wait();
+    let output = output.lock().unwrap();
+    eprintln!();
+    eprintln!(
+        "Error: {} timed out after {}s; last {} lines of output:",
+        name,
+        timeout.unwrap_or_default().as_secs(),
+        output.tail.len()
+    );
+    for line in output.tail.iter() {
+        eprintln!("  | {line}");
+    }
+    (None, output.exit_code)
+}
+
+/// Machine-readable summary of `cargo xtask run`, written by `--report`.
+struct RunReport<'a> {
+    arch: &'a str,
+    target: &'a str,
+    /// Size of the payload binary, unknown in a dry run that would build it.
+    payload_size: Option<u64>,
+    /// The FAT image, absent in initrd mode.
+    disk_image: Option<&'a Path>,
+    qemu: &'a QemuRun,
+    /// Wall time of the whole run, builds included.
+    elapsed: Duration,
+}
+
+impl RunReport<'_> {
+    /// Render the report as a single-line JSON object.
+    fn to_json(&self) -> String {
+        let disk_image = match self.disk_image {
+            Some(path) => json_string(&path.display().to_string()),
+            None => "null".into(),
+        };
+        let payload_size = match self.payload_size {
+            Some(size) => size.to_string(),
+            None => "null".into(),
+        };
+        let guest_exit_code = match self.qemu.guest_exit_code {
+            Some(code) => code.to_string(),
+            None => "null".into(),
+        };
+        format!(
+            "{{\"arch\":{},\"target\":{},\"payload_size\":{},\"disk_image\":{},\
+             \"qemu_command\":{},\"qemu_exit_code\":{},\"guest_exit_code\":{},\
+             \"elapsed_secs\":{:.3}}}",
+            json_string(self.arch),
+            json_string(self.target),
+            payload_size,
+            disk_image,
+            json_string(&self.qemu.command),
+            self.qemu.qemu_exit_code,
+            guest_exit_code,
+            self.elapsed.as_secs_f64(),
+        )
+    }
+
+    /// Write the report to `path`, or print it to stdout if `path` is `-`.
+    fn write(&self, path: &Path) {
+        let json = self.to_json();
+        if path == Path::new("-") {
+            println!("{json}");
+        } else if let Err(e) = std::fs::write(path, json + "\n") {
+            eprintln!("Error: failed to write report {}: {}", path.display(), e);
             process::exit(1);
-        });
-    if !status.success() {
-        process::exit(status.code().unwrap_or(1));
+        }
+    }
+}
+
+/// Quote `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Exit with an error if `arch` cannot boot in `boot_mode`.
+fn check_boot_mode(arch: &str, info: &ArchInfo, boot_mode: BootMode) {
+    if boot_mode == BootMode::Initrd && !info.has_dtb {
+        eprintln!(
+            "Error: --boot-mode initrd needs a device tree to locate the initrd, \
+             which {arch} does not provide. Supported: riscv64, aarch64"
+        );
+        process::exit(1);
     }
 }
 
 fn main() {
     let cli = Cli::parse();
     let root = project_root();
+    let names = bin_names(&root);
 
     match cli.command {
-        Cmd::Build { ref arch } => {
+        Cmd::Build {
+            ref arch,
+            profile,
+            boot_mode,
+            ref features,
+        } => {
             let info = arch_info(arch);
-            install_config(&root, arch);
-            let _payload = build_payload(&root, &info);
-            do_build(&root, &info);
+            check_boot_mode(arch, &info, boot_mode);
+            install_config(&root, arch, false);
+            let _payload =
+                build_payload_and_kernel(&root, &info, profile, boot_mode, features, &names, false);
+            kernel_elf(&root, &info, profile, &names, false);
             println!("Build complete for {arch} ({})", info.target);
         }
-        Cmd::Run { ref arch } => {
+        Cmd::Run {
+            ref arch,
+            profile,
+            boot_mode,
+            ref features,
+            ref payload_bin,
+            timeout,
+            ref report,
+            ref cpu,
+            ref cmdline,
+            dry_run,
+        } => {
+            let start = Instant::now();
             let info = arch_info(arch);
-            install_config(&root, arch);
-
-            // 1. Build payload (equivalent to `make payload`)
-            let payload_bin = build_payload(&root, &info);
+            check_boot_mode(arch, &info, boot_mode);
+            install_config(&root, arch, dry_run);
 
-            // 2. Create disk image with payload (equivalent to `./update_disk.sh`)
-            let disk = root.join("target").join("disk.img");
-            create_fat_disk_image(&disk, &payload_bin);
+            // 1. Build payload (equivalent to `make payload`), unless
+            // prebuilt, alongside the kernel (equivalent to
+            // `make run A=tour/m_1_0 BLK=y`)
+            let prebuilt = payload_bin.is_some();
+            let payload_bin = match payload_bin {
+                Some(path) => {
+                    let payload_bin = prebuilt_payload(path);
+                    do_build(&root, &info, profile, boot_mode, features, dry_run);
+                    payload_bin
+                }
+                None => build_payload_and_kernel(
+                    &root, &info, profile, boot_mode, features, &names, dry_run,
+                ),
+            };
+            // A dry run builds nothing, so whatever sits at the payload path
+            // would be stale.
+            let payload_size = (prebuilt || !dry_run)
+                .then(|| std::fs::metadata(&payload_bin).map_or(0, |meta| meta.len()));
 
-            // 3. Build kernel (equivalent to `make run A=tour/m_1_0 BLK=y`)
-            do_build(&root, &info);
+            // 2. Create disk image with payload (equivalent to `./update_disk.sh`);
+            // in initrd mode QEMU is handed the payload itself instead
+            let out_dir = output_dir(&root, arch);
+            let image = match boot_mode {
+                BootMode::Blk => {
+                    let disk = out_dir.join("disk.img");
+                    create_fat_disk_image(&disk, &payload_bin, dry_run);
+                    disk
+                }
+                BootMode::Initrd => payload_bin,
+            };
 
-            let elf = root
-                .join("target")
-                .join(info.target)
-                .join("release")
-                .join("arceos-userprivilege");
-            let bin = elf.with_extension("bin");
+            let elf = kernel_elf(&root, &info, profile, &names, dry_run);
+            let kernel = match info.boot_artifact {
+                BootArtifact::Elf => elf,
+                BootArtifact::Bin => {
+                    let bin = out_dir.join(format!("{}.bin", names.kernel));
+                    do_objcopy(&elf, &bin, info.objcopy_arch, dry_run);
+                    bin
+                }
+            };
 
-            if arch != "x86_64" {
-                do_objcopy(&elf, &bin, info.objcopy_arch);
+            let qemu = do_run_qemu(
+                arch,
+                &info,
+                &kernel,
+                boot_mode,
+                &image,
+                &QemuOptions {
+                    timeout,
+                    cpu: cpu.as_deref(),
+                    cmdline: cmdline.as_deref(),
+                    dry_run,
+                },
+            );
+            if let Some(path) = report {
+                RunReport {
+                    arch,
+                    target: info.target,
+                    payload_size,
+                    disk_image: (boot_mode == BootMode::Blk).then_some(image.as_path()),
+                    qemu: &qemu,
+                    elapsed: start.elapsed(),
+                }
+                .write(path);
+            }
+            if qemu.qemu_exit_code != 0 {
+                process::exit(qemu.qemu_exit_code);
             }
+        }
+    }
+}
 
-            do_run_qemu(arch, &elf, &bin, &disk);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(cmdline: Option<&str>) -> QemuOptions<'_> {
+        QemuOptions {
+            timeout: None,
+            cpu: None,
+            cmdline,
+            dry_run: true,
+        }
+    }
+
+    fn args(arch: &str, boot_mode: BootMode, opts: &QemuOptions) -> String {
+        let info = arch_info(arch);
+        qemu_args(
+            arch,
+            &info,
+            Path::new("/k"),
+            boot_mode,
+            Path::new("/img"),
+            opts,
+        )
+        .join(" ")
+    }
+
+    #[test]
+    fn qemu_args_per_arch() {
+        let common = "-m 128M -smp 1 -nographic";
+        let disk =
+            "-drive file=/img,format=raw,if=none,id=disk0 -device virtio-blk-pci,drive=disk0";
+        let cases = [
+            ("riscv64", "-machine virt -bios default -kernel /k"),
+            ("aarch64", "-machine virt -cpu cortex-a72 -kernel /k"),
+            ("x86_64", "-machine q35 -kernel /k"),
+            ("loongarch64", "-machine virt -kernel /k"),
+        ];
+        for (arch, machine) in cases {
+            assert_eq!(
+                args(arch, BootMode::Blk, &opts(None)),
+                format!("{common} {machine} {disk}"),
+                "{arch}"
+            );
+        }
+    }
+
+    #[test]
+    fn qemu_args_initrd() {
+        assert_eq!(
+            args("riscv64", BootMode::Initrd, &opts(None)),
+            "-m 128M -smp 1 -nographic -machine virt -bios default -kernel /k -initrd /img"
+        );
+    }
+
+    #[test]
+    fn qemu_args_cpu_override() {
+        let opts = QemuOptions {
+            cpu: Some("cortex-a53"),
+            ..opts(None)
+        };
+        let args = args("aarch64", BootMode::Blk, &opts);
+        assert!(args.contains("-cpu cortex-a53 "), "{args}");
+        assert!(!args.contains("cortex-a72"), "{args}");
+    }
+
+    #[test]
+    fn cmdline_only_appended_with_a_device_tree() {
+        for arch in ["riscv64", "aarch64"] {
+            let args = args(arch, BootMode::Blk, &opts(Some("log=debug")));
+            assert!(args.contains("-append log=debug"), "{arch}: {args}");
+        }
+        for arch in ["x86_64", "loongarch64"] {
+            let args = args(arch, BootMode::Blk, &opts(Some("log=debug")));
+            assert!(!args.contains("-append"), "{arch}: {args}");
         }
     }
+
+    #[test]
+    fn kernel_cargo_joins_features() {
+        let root = Path::new("/r");
+        let info = arch_info("riscv64");
+        let features = ["verify-load".to_string(), "allow-wx".to_string()];
+        let cmd = kernel_cargo(root, &info, Profile::Release, BootMode::Initrd, &features);
+        assert_eq!(
+            command_line(&cmd),
+            "AX_CONFIG_PATH=/r/.axconfig.toml cargo build --release \
+             --target riscv64gc-unknown-none-elf --features initrd,verify-load,allow-wx \
+             --manifest-path /r/Cargo.toml"
+        );
+        let cmd = kernel_cargo(root, &info, Profile::Debug, BootMode::Blk, &[]);
+        assert!(command_line(&cmd).ends_with(
+            "cargo build --target riscv64gc-unknown-none-elf --features blk \
+             --manifest-path /r/Cargo.toml"
+        ));
+    }
+
+    #[test]
+    fn payload_cargo_uses_its_own_target_dir() {
+        let names = BinNames {
+            kernel: "kernel".into(),
+            payload: "origin".into(),
+        };
+        let cmd = payload_cargo(
+            Path::new("/r"),
+            &arch_info("x86_64"),
+            Profile::Release,
+            &names,
+        );
+        assert_eq!(
+            command_line(&cmd),
+            "cargo build --release --target x86_64-unknown-none --bin origin \
+             --features payload --manifest-path /r/Cargo.toml --target-dir /r/target/payload"
+        );
+    }
+
+    #[test]
+    fn guest_exit_parsed_from_log_line() {
+        let line = "\x1b[37m[  0.1 0 arceos_userprivilege:110] \
+                    monolithic kernel exit [3] normally!\x1b[m";
+        assert_eq!(parse_guest_exit(line), Some(3));
+        assert_eq!(
+            parse_guest_exit("monolithic kernel exit [-9] normally!"),
+            Some(-9)
+        );
+        assert_eq!(
+            parse_guest_exit("payload: FP state survived a syscall"),
+            None
+        );
+    }
+
+    #[test]
+    fn dry_run_report_has_null_sizes_and_codes() {
+        let qemu = QemuRun {
+            command: "qemu-system-riscv64 -m 128M".into(),
+            qemu_exit_code: 0,
+            guest_exit_code: None,
+        };
+        let report = RunReport {
+            arch: "riscv64",
+            target: "riscv64gc-unknown-none-elf",
+            payload_size: None,
+            disk_image: None,
+            qemu: &qemu,
+            elapsed: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            report.to_json(),
+            "{\"arch\":\"riscv64\",\"target\":\"riscv64gc-unknown-none-elf\",\
+             \"payload_size\":null,\"disk_image\":null,\
+             \"qemu_command\":\"qemu-system-riscv64 -m 128M\",\"qemu_exit_code\":0,\
+             \"guest_exit_code\":null,\"elapsed_secs\":1.500}"
+        );
+    }
 }